@@ -10,7 +10,8 @@ fn main() -> Result<()> {
     stdout.suspend_raw_mode()?;
 
     let mut bag = Bag::new_rng7(123456);
-    let mut game = Game::from_bag(&mut bag);
+    let game = Game::from_bag(&mut bag);
+    let mut history = GameHistory::new(game, bag);
     println!("{game}");
 
     stdout.activate_raw_mode()?;
@@ -19,43 +20,58 @@ fn main() -> Result<()> {
         match key? {
             Key::Ctrl('c') => break,
             Key::Left => {
-                game.active.shift_left(&game.board);
+                history.apply(Action::ShiftLeft);
             }
             Key::Right => {
-                game.active.shift_right(&game.board);
+                history.apply(Action::ShiftRight);
             }
             Key::Down => {
-                game.active.soft_drop(&game.board);
+                history.apply(Action::SoftDrop);
             }
             Key::Char(' ') => {
-                let info = game.hard_drop();
+                let info = history.apply(Action::HardDrop);
                 if let ActionInfo::Lock(LockInfo {
                     lines_cleared,
                     top_out,
-                    tspin,
+                    spin,
+                    combo,
+                    attack,
+                    ..
                 }) = info
                 {
-                    println!("Cleared: {lines_cleared} T-Spin: {tspin} Top out: {top_out}");
+                    println!(
+                        "Cleared: {lines_cleared} Spin: {spin:?} Combo: {combo} Attack: {attack} Top out: {top_out}"
+                    );
                 }
             }
             Key::Char('a') => {
-                game.active.rotate_180(&game.board);
+                history.apply(Action::Rotate180);
             }
             Key::Char('z') => {
-                game.active.rotate_ccw(&game.board);
+                history.apply(Action::RotateCcw);
             }
             Key::Char('x') => {
-                game.active.rotate_cw(&game.board);
+                history.apply(Action::RotateCw);
             }
             Key::Char('c') => {
-                game.swap_hold();
+                history.apply(Action::Hold);
+            }
+            Key::Char('u') => {
+                history.undo();
+            }
+            Key::Char('r') => {
+                history.redo();
+            }
+            Key::Char('g') => {
+                // Garbage isn't an action, so it starts a new history
+                let mut game = *history.game();
+                game.board.add_garbage(6, 2);
+                history.reset(game);
             }
-            Key::Char('g') => game.board.add_garbage(6, 2),
             _ => {}
         }
 
-        game.refill_queue(&mut bag);
-        println!("{game}");
+        println!("{}", history.game());
         stdout.activate_raw_mode()?;
     }
     Ok(())
@@ -13,8 +13,23 @@ use std::{
 #[serde(from = "crate::serde::SerializedEvaluation")]
 #[serde(into = "crate::serde::SerializedEvaluation")]
 pub enum Evaluation {
-    Success { actions: Vec<Action>, score: f32 },
-    Fail { message: String },
+    Success {
+        actions: Vec<Action>,
+        score: f32,
+        stats: Option<SearchStats>,
+    },
+    Fail {
+        message: String,
+    },
+}
+
+/// How far an Ai searched to reach an evaluation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SearchStats {
+    /// Number of pieces looked ahead, counting the current piece
+    pub depth: u32,
+    /// Number of nodes expanded
+    pub nodes: u64,
 }
 
 /// An object that can evaluate Tetris game states
@@ -33,7 +48,11 @@ pub trait Ai {
             let elapsed = start.elapsed();
 
             match res {
-                Evaluation::Success { actions, score } => {
+                Evaluation::Success {
+                    actions,
+                    score,
+                    stats,
+                } => {
                     let mut top_out = false;
                     for &action in &actions {
                         if let Action::HardDrop = action {
@@ -52,6 +71,9 @@ pub trait Ai {
                     println!("{actions:?}");
                     println!("Evaluated in {elapsed:?}");
                     println!("Evaluation score: {score:0.2}");
+                    if let Some(SearchStats { depth, nodes }) = stats {
+                        println!("Searched {nodes} nodes to depth {depth}");
+                    }
                     if top_out {
                         break;
                     }
@@ -67,6 +89,37 @@ pub trait Ai {
     }
 }
 
+/// Evaluate and apply actions until a piece is locked, returns the lock info
+/// and the total time spent evaluating. Returns `None` if the ai failed to
+/// lock a piece. The queue is refilled from the bag before each evaluation
+/// and after the lock.
+pub(crate) fn play_piece(
+    ai: &mut dyn Ai,
+    game: &mut Game,
+    bag: &mut Bag,
+) -> Option<(LockInfo, Duration)> {
+    // Allow a few evaluations that don't lock, e.g. an initial hold
+    const MAX_EVALUATIONS: usize = 4;
+    let mut elapsed = Duration::ZERO;
+    for _ in 0..MAX_EVALUATIONS {
+        game.refill_queue(bag);
+        let start = Instant::now();
+        let res = ai.evaluate(game);
+        elapsed += start.elapsed();
+
+        let Evaluation::Success { actions, .. } = res else {
+            return None;
+        };
+        for action in actions {
+            if let ActionInfo::Lock(lock_info) = game.apply(action) {
+                game.refill_queue(bag);
+                return Some((lock_info, elapsed));
+            }
+        }
+    }
+    None
+}
+
 /// A very simple ai, useful for testing.
 /// Should never top out...
 #[derive(Debug)]
@@ -86,6 +139,7 @@ impl Ai for SimpleAi {
             return Evaluation::Success {
                 actions: vec![Action::HardDrop],
                 score: 0.,
+                stats: None,
             };
         }
         let mut best_child = None;
@@ -113,6 +167,7 @@ impl Ai for SimpleAi {
             Some(child) => Evaluation::Success {
                 actions: child.actions().collect(),
                 score: children.len() as f32,
+                stats: None,
             },
             None => Evaluation::Fail {
                 message: String::from("no valid game actions"),
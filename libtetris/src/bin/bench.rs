@@ -269,4 +269,10 @@ fn main() {
         let child_states = game.children(Fin::Full3);
         black_box(&child_states);
     });
+
+    bench_fn("gen_placements", count / 16, |i| {
+        let game = scenarios[i];
+        let placements = game.placements();
+        black_box(&placements);
+    });
 }
@@ -2,7 +2,14 @@ use anyhow::{bail, Result};
 use libtetris::*;
 use rand::{rngs::StdRng, RngCore, SeedableRng};
 
-/// Verify that the output of children is equal to the moves generated
+const ROTATION_SYSTEMS: [RotationSystem; 3] = [
+    RotationSystem::Srs,
+    RotationSystem::SrsPlus,
+    RotationSystem::Ars,
+];
+
+/// Verify that the output of children is equal to the moves generated, and
+/// that the placements found by the move generator include every child
 fn main() -> Result<()> {
     let count = 1_000_000;
     println!("Checking scenarios...\n0/{count}");
@@ -10,15 +17,19 @@ fn main() -> Result<()> {
         let mut rng = StdRng::seed_from_u64(i);
         let mut bag = Bag::new_rng7(i as u64);
         let mut game = Game::from_bag(&mut bag);
+        let rotation_system = ROTATION_SYSTEMS[i as usize % ROTATION_SYSTEMS.len()];
+        game.set_rotation_system(rotation_system);
         for _ in 0..5 {
             let children = game.children(Fin::Full3);
             // Check children
             for child in children.iter() {
                 let mut new_game = game;
+                let mut info = ActionInfo::Fail;
                 for actions in child.actions() {
-                    new_game.apply(actions);
+                    info = new_game.apply(actions);
                 }
-                if new_game != child.game {
+                if new_game != child.game || info != ActionInfo::Lock(child.lock_info) {
+                    println!("Rotation system: {rotation_system:?}");
                     println!("Begin:\n{game}");
                     println!("Sequence: {:?}", child.actions().collect::<Vec<_>>());
                     println!("Expected:\n{new_game}");
@@ -26,6 +37,35 @@ fn main() -> Result<()> {
                     bail!("mismatched games");
                 }
             }
+            // Check placements
+            let placements = game.placements();
+            for placement in placements.iter() {
+                let mut new_game = game;
+                let mut info = ActionInfo::Fail;
+                for &action in placement.actions.iter() {
+                    info = new_game.apply(action);
+                }
+                if new_game != placement.game || info != ActionInfo::Lock(placement.lock_info) {
+                    println!("Rotation system: {rotation_system:?}");
+                    println!("Begin:\n{game}");
+                    println!("Sequence: {:?}", placement.actions);
+                    println!("Expected:\n{new_game}");
+                    println!("Got:\n{}", placement.game);
+                    bail!("mismatched placement");
+                }
+            }
+            for child in children.iter() {
+                let found = placements.iter().any(|placement| {
+                    placement.game == child.game && placement.lock_info == child.lock_info
+                });
+                if !found {
+                    println!("Rotation system: {rotation_system:?}");
+                    println!("Begin:\n{game}");
+                    println!("Sequence: {:?}", child.actions().collect::<Vec<_>>());
+                    println!("Expected:\n{}", child.game);
+                    bail!("child missing from placements");
+                }
+            }
             // Set game to random child
             let idx = rng.next_u64() as usize % children.len();
             game = children[idx].game;
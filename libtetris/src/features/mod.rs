@@ -0,0 +1,275 @@
+use crate::model::{Board, ColumnMap, Piece, PieceType};
+
+// Board features commonly used by evaluators, computed on the bitboard. Rows
+// above `Board::max_height` are empty, so most features only scan the rows
+// below it.
+
+impl Board {
+    /// For each row up to the max height, whether each tile is empty with a
+    /// filled tile somewhere above it
+    fn hole_rows(&self) -> Vec<u16> {
+        let full = self.full_row();
+        let mut above = 0;
+        let mut rows = vec![0; self.max_height() as usize];
+        for y in (0..rows.len()).rev() {
+            rows[y] = !self.matrix[y] & above & full;
+            above |= self.matrix[y];
+        }
+        rows
+    }
+
+    /// Number of horizontally adjacent pairs of tiles where one is filled and
+    /// the other is empty, summed over rows up to the max height. The walls
+    /// count as filled.
+    pub fn row_transitions(&self) -> u32 {
+        let width = self.width();
+        let walls = 1 | 1 << (width + 1);
+        let mask = (1 << (width + 1)) - 1;
+        (0..self.max_height() as usize)
+            .map(|y| {
+                let row = (self.matrix[y] as u32) << 1 | walls;
+                ((row ^ row >> 1) & mask).count_ones()
+            })
+            .sum()
+    }
+
+    /// Number of vertically adjacent pairs of tiles where one is filled and
+    /// the other is empty, summed over columns. The floor counts as filled.
+    pub fn column_transitions(&self) -> u32 {
+        let top = (self.max_height() as usize + 1).min(self.height());
+        let mut below = self.full_row();
+        let mut transitions = 0;
+        for &row in &self.matrix[..top] {
+            transitions += (row ^ below).count_ones();
+            below = row;
+        }
+        transitions
+    }
+
+    /// Number of rows that contain at least one hole
+    pub fn rows_with_holes(&self) -> u32 {
+        self.hole_rows().iter().filter(|&&row| row != 0).count() as u32
+    }
+
+    /// Number of filled tiles that have a hole somewhere below them
+    pub fn covered_cells(&self) -> u32 {
+        let mut below = 0;
+        let mut covered = 0;
+        for (&row, &holes) in self.matrix.iter().zip(self.hole_rows().iter()) {
+            covered += (row & below).count_ones();
+            below |= holes;
+        }
+        covered
+    }
+
+    /// Sum over every hole of the number of filled tiles above it
+    pub fn hole_depth(&self) -> u32 {
+        let mut depth = 0;
+        for x in 0..self.width() {
+            let mut filled = 0;
+            for y in (0..self.max_height() as usize).rev() {
+                if self.get(x, y) {
+                    filled += 1;
+                } else {
+                    depth += filled;
+                }
+            }
+        }
+        depth
+    }
+
+    /// Number of empty tiles directly below a filled tile
+    pub fn overhangs(&self) -> u32 {
+        let full = self.full_row();
+        self.matrix[..self.max_height() as usize]
+            .windows(2)
+            .map(|rows| (!rows[0] & rows[1] & full).count_ones())
+            .sum()
+    }
+
+    /// How far each column is below both of its neighbours, the walls are
+    /// infinitely high
+    pub fn well_depths(&self) -> ColumnMap {
+        let heights = self.height_map();
+        let width = self.width();
+        ColumnMap::from_fn(width, |x| {
+            let left = if x == 0 { i8::MAX } else { heights[x - 1] };
+            let right = if x == width - 1 {
+                i8::MAX
+            } else {
+                heights[x + 1]
+            };
+            (left.min(right) - heights[x]).max(0)
+        })
+    }
+
+    /// Dellacherie's cumulative wells, a well of depth `d` counts as
+    /// `1 + 2 + ... + d`
+    pub fn well_sums(&self) -> u32 {
+        self.well_depths()
+            .into_iter()
+            .map(|depth| (depth as u32 * (depth as u32 + 1)) / 2)
+            .sum()
+    }
+
+    /// Difference between the number of filled tiles on the dark and light
+    /// squares of a checkerboard
+    pub fn parity(&self) -> u32 {
+        let mut dark = 0;
+        let mut light = 0;
+        for (y, &row) in self.matrix[..self.max_height() as usize].iter().enumerate() {
+            let mask = if y % 2 == 0 { 0x5555 } else { 0xAAAA };
+            dark += (row & mask).count_ones();
+            light += (row & !mask).count_ones();
+        }
+        dark.abs_diff(light)
+    }
+
+    /// Number of T piece placements that rest on the stack and would clear
+    /// `lines` lines as a full t-spin, 2 for TSD slots and 3 for TST slots.
+    /// Slots don't need to be reachable from the spawn position.
+    pub fn t_slots(&self, lines: u32) -> u32 {
+        let max_height = self.max_height();
+        let mut slots = 0;
+        for rotation in 0..4 {
+            let piece = Piece::from_parts(PieceType::T, rotation, 0, 0);
+            let (left, right, bottom, top) =
+                piece
+                    .rotation_system
+                    .location_bound(PieceType::T, rotation, self);
+            for x in left..=right {
+                for y in bottom..=top.min(max_height) {
+                    let piece = Piece::from_parts(PieceType::T, rotation, x, y);
+                    if self.is_t_slot(&piece, lines) {
+                        slots += 1;
+                    }
+                }
+            }
+        }
+        slots
+    }
+
+    fn is_t_slot(&self, piece: &Piece, lines: u32) -> bool {
+        let mut below = *piece;
+        if self.intersects_with(piece) || below.shift(0, -1, self) {
+            return false;
+        }
+        if self.lines_cleared_by(piece) != lines {
+            return false;
+        }
+        // The same corners as the 3-corner rule, the two corners the T
+        // points towards must be filled except for TSTs, which are always
+        // full spins due to their kick
+        let filled = |d_x: i8, d_y: i8| {
+            let (x, y) = (piece.position_x + 1 + d_x, piece.position_y + 1 + d_y);
+            x < 0
+                || y < 0
+                || x >= self.width() as i8
+                || (y < self.height() as i8 && self.get(x as usize, y as usize))
+        };
+        let corners = [(-1, 1), (1, 1), (1, -1), (-1, -1)];
+        let filled_corners = corners.iter().filter(|&&(x, y)| filled(x, y)).count();
+        let (a, b) = match piece.rotation {
+            0 => (corners[0], corners[1]),
+            1 => (corners[1], corners[2]),
+            2 => (corners[2], corners[3]),
+            _ => (corners[3], corners[0]),
+        };
+        let front = filled(a.0, a.1) && filled(b.0, b.1);
+        filled_corners >= 3 && (front || lines == 3)
+    }
+
+    /// The rows of a piece's shape that would be cleared by locking it, rows
+    /// that aren't cleared are 0
+    fn cleared_rows(&self, piece: &Piece) -> [u16; 4] {
+        let shape =
+            piece
+                .rotation_system
+                .bit_shape(piece.piece_type, piece.rotation, piece.position_x);
+        let mut rows = [0; 4];
+        for (j, (row, shape)) in rows.iter_mut().zip(shape).enumerate() {
+            let y = piece.position_y as i32 + j as i32;
+            if shape != 0
+                && (0..self.height() as i32).contains(&y)
+                && self.matrix[y as usize] | shape == self.full_row()
+            {
+                *row = shape;
+            }
+        }
+        rows
+    }
+
+    fn lines_cleared_by(&self, piece: &Piece) -> u32 {
+        self.cleared_rows(piece)
+            .iter()
+            .filter(|&&row| row != 0)
+            .count() as u32
+    }
+
+    /// Dellacherie's eroded piece cells, the number of lines a piece clears
+    /// multiplied by the number of its tiles in those lines
+    pub fn eroded_cells(&self, piece: &Piece) -> u32 {
+        let rows = self.cleared_rows(piece);
+        let tiles = rows.iter().map(|row| row.count_ones()).sum::<u32>();
+        self.lines_cleared_by(piece) * tiles
+    }
+}
+
+impl Piece {
+    /// Dellacherie's landing height, the height of the centre of the piece
+    /// above the floor
+    pub fn landing_height(&self) -> f32 {
+        let shape = self
+            .rotation_system
+            .bit_shape(self.piece_type, self.rotation, self.position_x);
+        let rows = (0..4).filter(|&j| shape[j] != 0);
+        let bottom = rows.clone().min().unwrap() as i32 + self.position_y as i32;
+        let top = rows.max().unwrap() as i32 + self.position_y as i32;
+        (bottom + top + 1) as f32 / 2.
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const FULL: u16 = (1 << 10) - 1;
+
+    #[test]
+    fn test_board_features() {
+        // Hole in column 2 under a ledge, and a well in column 9
+        let mut board = Board::new();
+        board.set_row(0, FULL & !(1 << 2) & !(1 << 9));
+        board.set_row(1, 0b111);
+        assert_eq!(board.row_transitions(), 6);
+        assert_eq!(board.column_transitions(), 12);
+        assert_eq!(board.rows_with_holes(), 1);
+        assert_eq!(board.covered_cells(), 1);
+        assert_eq!(board.hole_depth(), 1);
+        assert_eq!(board.overhangs(), 1);
+        assert_eq!(&*board.well_depths(), &[0, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+        assert_eq!(board.well_sums(), 1);
+        assert_eq!(board.parity(), 1);
+        assert_eq!(board.t_slots(2), 0);
+
+        // T-spin double slot with an overhang on the left
+        let mut board = Board::new();
+        board.set_row(0, FULL & !(1 << 2));
+        board.set_row(1, FULL & !(0b111 << 1));
+        board.set_row(2, 1 << 1);
+        assert_eq!(board.t_slots(2), 1);
+        assert_eq!(board.t_slots(3), 0);
+        let piece = Piece::from_parts(PieceType::T, 2, 1, 0);
+        assert_eq!(piece.landing_height(), 1.);
+        assert_eq!(board.eroded_cells(&piece), 8);
+
+        // T-spin triple slot, the T points left into column 2
+        let mut board = Board::new();
+        board.set_row(0, FULL & !(1 << 3));
+        board.set_row(1, FULL & !(0b11 << 2));
+        board.set_row(2, FULL & !(1 << 3));
+        assert_eq!(board.t_slots(3), 1);
+        let piece = Piece::from_parts(PieceType::T, 3, 2, 0);
+        assert_eq!(board.eroded_cells(&piece), 12);
+    }
+}
@@ -0,0 +1,733 @@
+use crate::{Board, Game, Piece, PieceType, RotationSystem};
+use anyhow::{anyhow, bail, Result};
+
+const FIELD_WIDTH: usize = 10;
+/// Number of rows in a fumen field, not counting the garbage row
+const FIELD_HEIGHT: usize = 23;
+const FIELD_BLOCKS: usize = (FIELD_HEIGHT + 1) * FIELD_WIDTH;
+const GRAY: u8 = 8;
+const ENCODE_TABLE: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+const COMMENT_TABLE: &[u8] =
+    b" !\"#$%&'()*+,-./0123456789:;<=>?@ABCDEFGHIJKLMNOPQRSTUVWXYZ[\\]^_`abcdefghijklmnopqrstuvwxyz{|}~";
+const COMMENT_BASE: u32 = COMMENT_TABLE.len() as u32 + 1;
+const QUIZ_PREFIX: &str = "#Q=";
+
+/// A colored fumen field, indexed from the top left. The garbage row is last.
+type Field = [u8; FIELD_BLOCKS];
+
+/// A single page of a fumen
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FumenPage {
+    /// The board before the piece is placed
+    pub board: Board,
+    /// The garbage row below the board, as a bitmask
+    pub garbage: u16,
+    /// The piece placed on this page
+    pub piece: Option<Piece>,
+    /// The comment of this page, `None` if the previous comment continues
+    pub comment: Option<String>,
+    /// Whether the piece is locked and lines are cleared for the next page
+    pub lock: bool,
+    /// Whether the garbage row rises into the board for the next page
+    pub rise: bool,
+    /// Whether the board is mirrored for the next page
+    pub mirror: bool,
+}
+
+impl FumenPage {
+    pub fn new(board: Board) -> Self {
+        FumenPage {
+            board,
+            garbage: 0,
+            piece: None,
+            comment: None,
+            lock: true,
+            rise: false,
+            mirror: false,
+        }
+    }
+}
+
+/// A list of pages in the fumen v115 format used to share boards
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Fumen {
+    pub pages: Vec<FumenPage>,
+}
+
+impl Fumen {
+    pub fn new() -> Self {
+        Fumen { pages: Vec::new() }
+    }
+
+    /// Create a fumen with a single page showing a game, the queue is
+    /// written as a `#Q=` quiz comment
+    pub fn from_game(game: &Game) -> Self {
+        let mut fumen = Fumen::new();
+        fumen.push_game(game, None);
+        fumen
+    }
+
+    /// Add a page showing a game and the piece placed on it, the queue is
+    /// written as a `#Q=` quiz comment
+    pub fn push_game(&mut self, game: &Game, piece: Option<Piece>) {
+        let mut page = FumenPage::new(game.board);
+        page.piece = piece;
+        page.comment = Some(quiz_comment(game));
+        self.pages.push(page);
+    }
+
+    /// The game before the piece is placed on each page. The first page
+    /// needs a `#Q=` quiz comment with the queue, later pages without one
+    /// continue from the piece placed on the previous page.
+    pub fn games(&self) -> Result<Vec<Game>> {
+        let mut games: Vec<Game> = Vec::new();
+        for (i, page) in self.pages.iter().enumerate() {
+            let quiz = page
+                .comment
+                .as_deref()
+                .and_then(|comment| comment.strip_prefix(QUIZ_PREFIX));
+            let mut game = match (quiz, games.last()) {
+                (Some(quiz), _) => parse_quiz(quiz)?,
+                (None, Some(&prev)) => match self.pages[i - 1].piece {
+                    Some(piece) => next_game(prev, piece.piece_type)
+                        .ok_or_else(|| anyhow!("page {} places a piece not in the queue", i - 1))?,
+                    None => prev,
+                },
+                (None, None) => bail!("first page has no {QUIZ_PREFIX} comment"),
+            };
+            game.set_board(page.board);
+            games.push(game);
+        }
+        Ok(games)
+    }
+
+    /// Decode a fumen string, such as `v115@vhAAgH`. Surrounding text like
+    /// a url is ignored.
+    pub fn decode(text: &str) -> Result<Self> {
+        let start = ["v115@", "m115@", "d115@", "D115@"]
+            .iter()
+            .filter_map(|prefix| text.find(prefix))
+            .min()
+            .ok_or_else(|| anyhow!("unsupported fumen version"))?;
+        let data = text[start + 5..]
+            .chars()
+            .filter(|&c| c != '?')
+            .take_while(|c| !c.is_whitespace() && *c != '&' && *c != '#')
+            .map(|c| {
+                ENCODE_TABLE
+                    .iter()
+                    .position(|&x| x as char == c)
+                    .map(|x| x as u8)
+                    .ok_or_else(|| anyhow!("unexpected character {c:?} in fumen"))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut values = Values { data, head: 0 };
+        let mut pages = Vec::new();
+        let mut prev = [0; FIELD_BLOCKS];
+        let mut repeat = 0;
+        while !values.finished() {
+            // Field
+            let mut field = prev;
+            if repeat > 0 {
+                repeat -= 1;
+            } else {
+                let mut index = 0;
+                let mut changed = true;
+                while index < FIELD_BLOCKS {
+                    let run = values.poll(2)? as usize;
+                    let diff = (run / FIELD_BLOCKS) as i32 - GRAY as i32;
+                    let count = run % FIELD_BLOCKS + 1;
+                    if diff == 0 && count == FIELD_BLOCKS {
+                        changed = false;
+                    }
+                    if index + count > FIELD_BLOCKS {
+                        bail!("fumen field data is too long");
+                    }
+                    for cell in field[index..index + count].iter_mut() {
+                        let value = *cell as i32 + diff;
+                        if !(0..=GRAY as i32).contains(&value) {
+                            bail!("invalid fumen field value {value}");
+                        }
+                        *cell = value as u8;
+                    }
+                    index += count;
+                }
+                if !changed {
+                    repeat = values.poll(1)?;
+                }
+            }
+
+            // Action
+            let mut value = values.poll(3)?;
+            let piece_type = value % 8;
+            value /= 8;
+            let rotation = FUMEN_ROTATIONS[(value % 4) as usize];
+            value /= 4;
+            let coordinate = (value % FIELD_BLOCKS as u32) as i32;
+            value /= FIELD_BLOCKS as u32;
+            let rise = value % 2 == 1;
+            let mirror = (value >> 1) % 2 == 1;
+            let has_comment = (value >> 3) % 2 == 1;
+            let lock = (value >> 4) % 2 == 0;
+            let piece = match piece_type {
+                0 => None,
+                x => {
+                    let piece_type = piece_from_fumen(x as u8)?;
+                    let x = (coordinate % FIELD_WIDTH as i32) as i8;
+                    let y = (FIELD_HEIGHT as i32 - 1 - coordinate / FIELD_WIDTH as i32) as i8;
+                    let (d_x, d_y) = center_offset(piece_type, rotation);
+                    Some(piece_from_center(piece_type, rotation, x + d_x, y + d_y)?)
+                }
+            };
+
+            // Comment
+            let comment = if has_comment {
+                let len = values.poll(2)? as usize;
+                let mut escaped = String::new();
+                for _ in 0..len.div_ceil(4) {
+                    let mut value = values.poll(5)?;
+                    for _ in 0..4 {
+                        if escaped.len() == len {
+                            break;
+                        }
+                        let c = COMMENT_TABLE
+                            .get((value % COMMENT_BASE) as usize)
+                            .ok_or_else(|| anyhow!("invalid fumen comment character"))?;
+                        escaped.push(*c as char);
+                        value /= COMMENT_BASE;
+                    }
+                }
+                Some(unescape(&escaped)?)
+            } else {
+                None
+            };
+
+            let mut board = Board::new();
+            for y in 0..FIELD_HEIGHT {
+                board.set_row(y, field_row(&field, y as i8));
+            }
+            let page = FumenPage {
+                board,
+                garbage: field_row(&field, -1),
+                piece,
+                comment,
+                lock,
+                rise,
+                mirror,
+            };
+            prev = field;
+            apply_page(&mut prev, &page);
+            pages.push(page);
+        }
+        Ok(Fumen { pages })
+    }
+
+    /// Encode the pages into a fumen v115 string. Boards must be 10 wide and
+    /// fit within 23 rows.
+    pub fn encode(&self) -> Result<String> {
+        let mut values = Vec::new();
+        let mut prev = [0; FIELD_BLOCKS];
+        let mut repeat: Option<usize> = None;
+        for (i, page) in self.pages.iter().enumerate() {
+            // Field, cells keep their color from the previous page
+            if page.board.width() != FIELD_WIDTH {
+                bail!("fumen boards must be {FIELD_WIDTH} wide");
+            }
+            if page.board.matrix[FIELD_HEIGHT..]
+                .iter()
+                .any(|&row| row != 0)
+            {
+                bail!("fumen boards must fit within {FIELD_HEIGHT} rows");
+            }
+            let mut field = [0; FIELD_BLOCKS];
+            for (index, cell) in field.iter_mut().enumerate() {
+                let x = index % FIELD_WIDTH;
+                let y = FIELD_HEIGHT as i8 - 1 - (index / FIELD_WIDTH) as i8;
+                let row = match y {
+                    -1 => page.garbage,
+                    y => page.board.matrix[y as usize],
+                };
+                if row & (1 << x) != 0 {
+                    *cell = if prev[index] != 0 { prev[index] } else { GRAY };
+                }
+            }
+
+            let mut runs = Vec::new();
+            let mut index = 0;
+            while index < FIELD_BLOCKS {
+                let diff = field[index] as u32 + GRAY as u32 - prev[index] as u32;
+                let mut count = 1;
+                while index + count < FIELD_BLOCKS
+                    && field[index + count] as u32 + GRAY as u32 - prev[index + count] as u32
+                        == diff
+                {
+                    count += 1;
+                }
+                runs.push(diff * FIELD_BLOCKS as u32 + count as u32 - 1);
+                index += count;
+            }
+            let unchanged =
+                runs.len() == 1 && runs[0] == (GRAY as u32 + 1) * FIELD_BLOCKS as u32 - 1;
+            match repeat {
+                Some(index) if unchanged && values[index] < 63 => values[index] += 1,
+                _ => {
+                    for run in runs {
+                        push_value(&mut values, run, 2);
+                    }
+                    repeat = None;
+                    if unchanged {
+                        values.push(0);
+                        repeat = Some(values.len() - 1);
+                    }
+                }
+            }
+
+            // Action
+            let (piece_type, rotation, coordinate) = match page.piece {
+                Some(piece) => {
+                    let (rotation, x, y) = piece_center(&piece)?;
+                    let (d_x, d_y) = center_offset(piece.piece_type, rotation);
+                    let (x, y) = ((x - d_x) as i32, (y - d_y) as i32);
+                    if !(0..FIELD_WIDTH as i32).contains(&x)
+                        || !(-1..FIELD_HEIGHT as i32).contains(&y)
+                    {
+                        bail!("piece {piece} is outside of the fumen field");
+                    }
+                    let coordinate = (FIELD_HEIGHT as i32 - 1 - y) * FIELD_WIDTH as i32 + x;
+                    let rotation = FUMEN_ROTATIONS.iter().position(|&r| r == rotation).unwrap();
+                    (piece_to_fumen(piece.piece_type), rotation, coordinate)
+                }
+                None => (0, 0, 0),
+            };
+            let flags = [
+                !page.lock,
+                page.comment.is_some(),
+                i == 0,
+                page.mirror,
+                page.rise,
+            ];
+            let mut value = flags.iter().fold(0, |value, &flag| value * 2 + flag as u32);
+            value = value * FIELD_BLOCKS as u32 + coordinate as u32;
+            value = value * 4 + rotation as u32;
+            value = value * 8 + piece_type as u32;
+            push_value(&mut values, value, 3);
+
+            // Comment
+            if let Some(comment) = &page.comment {
+                let escaped = escape(comment);
+                if escaped.len() >= 4096 {
+                    bail!("fumen comment is too long");
+                }
+                push_value(&mut values, escaped.len() as u32, 2);
+                for chunk in escaped.as_bytes().chunks(4) {
+                    let value = chunk.iter().rev().fold(0, |value, &c| {
+                        let index = COMMENT_TABLE.iter().position(|&x| x == c).unwrap();
+                        value * COMMENT_BASE + index as u32
+                    });
+                    push_value(&mut values, value, 5);
+                }
+            }
+
+            prev = field;
+            apply_page(&mut prev, page);
+        }
+
+        // The first 42 characters are followed by groups of 47, split by '?'
+        let data = values
+            .iter()
+            .map(|&x| ENCODE_TABLE[x as usize] as char)
+            .collect::<String>();
+        let mut text = String::from("v115@");
+        for (i, c) in data.chars().enumerate() {
+            if i >= 42 && (i - 42) % 47 == 0 {
+                text.push('?');
+            }
+            text.push(c);
+        }
+        Ok(text)
+    }
+}
+
+/// Fumen rotations in order of their encoded value: reverse, right, spawn,
+/// left
+const FUMEN_ROTATIONS: [i8; 4] = [2, 1, 0, 3];
+
+/// Reads base 64 values from fumen data
+struct Values {
+    data: Vec<u8>,
+    head: usize,
+}
+
+impl Values {
+    /// Read a number stored in `len` characters, least significant first
+    fn poll(&mut self, len: usize) -> Result<u32> {
+        if self.head + len > self.data.len() {
+            bail!("fumen data ended unexpectedly");
+        }
+        let value = self.data[self.head..self.head + len]
+            .iter()
+            .rev()
+            .fold(0, |value, &x| value * 64 + x as u32);
+        self.head += len;
+        Ok(value)
+    }
+
+    fn finished(&self) -> bool {
+        self.head >= self.data.len()
+    }
+}
+
+fn push_value(values: &mut Vec<u8>, mut value: u32, len: usize) {
+    for _ in 0..len {
+        values.push((value % 64) as u8);
+        value /= 64;
+    }
+}
+
+fn field_row(field: &Field, y: i8) -> u16 {
+    let start = (FIELD_HEIGHT as i8 - 1 - y) as usize * FIELD_WIDTH;
+    (0..FIELD_WIDTH)
+        .filter(|&x| field[start + x] != 0)
+        .fold(0, |row, x| row | 1 << x)
+}
+
+/// Lock the piece of a page into the field, then clear lines, raise the
+/// garbage row and mirror the field as the page asks
+fn apply_page(field: &mut Field, page: &FumenPage) {
+    if !page.lock {
+        return;
+    }
+    if let Some(piece) = page.piece {
+        for (x, y) in piece_cells(&piece) {
+            if (0..FIELD_WIDTH as i8).contains(&x) && (-1..FIELD_HEIGHT as i8).contains(&y) {
+                let index = (FIELD_HEIGHT as i8 - 1 - y) as usize * FIELD_WIDTH + x as usize;
+                field[index] = piece_to_fumen(piece.piece_type);
+            }
+        }
+    }
+
+    // Rows of the playing field from the bottom up, without the garbage row
+    let mut rows = field[..FIELD_HEIGHT * FIELD_WIDTH]
+        .chunks(FIELD_WIDTH)
+        .rev()
+        .filter(|row| row.contains(&0))
+        .map(|row| row.to_vec())
+        .collect::<Vec<_>>();
+    let garbage = field[FIELD_HEIGHT * FIELD_WIDTH..].to_vec();
+    if page.rise {
+        rows.insert(0, garbage.clone());
+    }
+    if page.mirror {
+        for row in rows.iter_mut() {
+            row.reverse();
+        }
+    }
+    rows.resize(FIELD_HEIGHT, vec![0; FIELD_WIDTH]);
+    for (i, row) in rows.iter().enumerate() {
+        let start = (FIELD_HEIGHT - 1 - i) * FIELD_WIDTH;
+        field[start..start + FIELD_WIDTH].copy_from_slice(row);
+    }
+    if page.rise {
+        field[FIELD_HEIGHT * FIELD_WIDTH..].fill(0);
+    }
+}
+
+fn piece_to_fumen(piece_type: PieceType) -> u8 {
+    match piece_type {
+        PieceType::I => 1,
+        PieceType::L => 2,
+        PieceType::O => 3,
+        PieceType::Z => 4,
+        PieceType::T => 5,
+        PieceType::J => 6,
+        PieceType::S => 7,
+    }
+}
+
+fn piece_from_fumen(value: u8) -> Result<PieceType> {
+    match value {
+        1 => Ok(PieceType::I),
+        2 => Ok(PieceType::L),
+        3 => Ok(PieceType::O),
+        4 => Ok(PieceType::Z),
+        5 => Ok(PieceType::T),
+        6 => Ok(PieceType::J),
+        7 => Ok(PieceType::S),
+        x => bail!("invalid fumen piece {x}"),
+    }
+}
+
+/// Offset from the position stored in a fumen to the rotation center of
+/// the piece
+fn center_offset(piece_type: PieceType, rotation: i8) -> (i8, i8) {
+    match (piece_type, rotation) {
+        (PieceType::O, 0) => (0, -1),
+        (PieceType::O, 2) => (1, 0),
+        (PieceType::O, 3) => (1, -1),
+        (PieceType::I, 2) => (1, 0),
+        (PieceType::I, 3) => (0, -1),
+        (PieceType::S, 0) => (0, -1),
+        (PieceType::S, 1) => (-1, 0),
+        (PieceType::Z, 0) => (0, -1),
+        (PieceType::Z, 3) => (1, 0),
+        _ => (0, 0),
+    }
+}
+
+/// Tiles of a fumen piece relative to its rotation center
+fn fumen_shape(piece_type: PieceType, rotation: i8) -> [(i8, i8); 4] {
+    let mut shape = match piece_type {
+        PieceType::I => [(0, 0), (-1, 0), (1, 0), (2, 0)],
+        PieceType::T => [(0, 0), (-1, 0), (1, 0), (0, 1)],
+        PieceType::O => [(0, 0), (1, 0), (0, 1), (1, 1)],
+        PieceType::L => [(0, 0), (-1, 0), (1, 0), (1, 1)],
+        PieceType::J => [(0, 0), (-1, 0), (1, 0), (-1, 1)],
+        PieceType::S => [(0, 0), (-1, 0), (0, 1), (1, 1)],
+        PieceType::Z => [(0, 0), (1, 0), (0, 1), (-1, 1)],
+    };
+    for _ in 0..rotation {
+        for tile in shape.iter_mut() {
+            *tile = (tile.1, -tile.0);
+        }
+    }
+    shape
+}
+
+/// Tiles of a piece on the board
+fn piece_cells(piece: &Piece) -> Vec<(i8, i8)> {
+    let shape = piece
+        .rotation_system
+        .shape(piece.piece_type, piece.rotation);
+    let mut cells = Vec::new();
+    for (i, column) in shape.iter().enumerate() {
+        for (j, &filled) in column.iter().enumerate() {
+            if filled {
+                cells.push((piece.position_x + i as i8, piece.position_y + j as i8));
+            }
+        }
+    }
+    cells
+}
+
+/// Sort tiles and move them so the lowest, leftmost tile is at the origin.
+/// Returns the offset that was removed.
+fn normalize(tiles: &mut [(i8, i8)]) -> (i8, i8) {
+    tiles.sort_by_key(|&(x, y)| (y, x));
+    let (min_x, min_y) = tiles[0];
+    for tile in tiles.iter_mut() {
+        *tile = (tile.0 - min_x, tile.1 - min_y);
+    }
+    (min_x, min_y)
+}
+
+/// Find the piece whose tiles match a fumen piece
+fn piece_from_center(piece_type: PieceType, rotation: i8, x: i8, y: i8) -> Result<Piece> {
+    let mut tiles = fumen_shape(piece_type, rotation).map(|(d_x, d_y)| (x + d_x, y + d_y));
+    let (min_x, min_y) = normalize(&mut tiles);
+    let mut piece = Piece {
+        piece_type,
+        rotation,
+        position_x: 0,
+        position_y: 0,
+        last_kick: None,
+        last_rotation: None,
+        rotation_system: RotationSystem::Srs,
+    };
+    let mut cells = piece_cells(&piece);
+    let (offset_x, offset_y) = normalize(&mut cells);
+    if cells != tiles {
+        bail!("fumen piece {piece_type} does not match its rotation");
+    }
+    piece.position_x = min_x - offset_x;
+    piece.position_y = min_y - offset_y;
+    Ok(piece)
+}
+
+/// Find the fumen rotation and rotation center of a piece, rotations are
+/// matched by their shape so pieces from any rotation system can be used
+fn piece_center(piece: &Piece) -> Result<(i8, i8, i8)> {
+    let mut cells = piece_cells(piece);
+    let (min_x, min_y) = normalize(&mut cells);
+    let rotations = [piece.rotation, 0, 1, 2, 3];
+    for rotation in rotations {
+        let mut tiles = fumen_shape(piece.piece_type, rotation);
+        let (offset_x, offset_y) = normalize(&mut tiles);
+        if tiles.as_slice() == cells {
+            return Ok((rotation, min_x - offset_x, min_y - offset_y));
+        }
+    }
+    bail!("piece {piece} does not match a fumen rotation")
+}
+
+fn quiz_comment(game: &Game) -> String {
+    let mut comment = String::from(QUIZ_PREFIX);
+    comment.push('[');
+    if let Some(hold) = game.hold {
+        comment.push_str(&hold.to_string());
+    }
+    comment.push_str(&format!("]({})", game.active.piece_type));
+    for i in 0..game.queue.len() {
+        comment.push_str(&game.queue.get(i).to_string());
+    }
+    comment
+}
+
+fn piece_from_char(c: char) -> Result<PieceType> {
+    PieceType::ALL
+        .into_iter()
+        .find(|piece_type| piece_type.to_string() == c.to_string())
+        .ok_or_else(|| anyhow!("unknown piece {c:?} in quiz comment"))
+}
+
+/// Parse a quiz comment without its prefix, such as `[T](I)LOSZ`
+fn parse_quiz(quiz: &str) -> Result<Game> {
+    let quiz = quiz.trim();
+    let (hold, rest) = quiz
+        .strip_prefix('[')
+        .and_then(|rest| rest.split_once(']'))
+        .ok_or_else(|| anyhow!("quiz comment is missing the hold piece"))?;
+    let (active, queue) = rest
+        .strip_prefix('(')
+        .and_then(|rest| rest.split_once(')'))
+        .ok_or_else(|| anyhow!("quiz comment is missing the current piece"))?;
+    let hold = hold.chars().next().map(piece_from_char).transpose()?;
+    let active = match active.chars().next() {
+        Some(c) => piece_from_char(c)?,
+        None => bail!("quiz comment has no current piece"),
+    };
+    let queue = queue
+        .chars()
+        .map(piece_from_char)
+        .collect::<Result<Vec<_>>>()?;
+    if queue.len() > crate::PIECE_QUEUE_MAX_LEN {
+        bail!("quiz comment queue is too long");
+    }
+    Ok(Game::from_pieces(active, hold, &queue))
+}
+
+/// The game after placing a piece of the given type, holding if needed.
+/// Returns `None` if the piece is not available.
+fn next_game(mut game: Game, piece_type: PieceType) -> Option<Game> {
+    if game.active.piece_type != piece_type {
+        game.swap_hold();
+    }
+    if game.active.piece_type != piece_type {
+        return None;
+    }
+    game.active.piece_type = game.queue.dequeue()?;
+    game.can_hold = true;
+    Some(game)
+}
+
+/// Escape text the same way as javascript's `escape`
+fn escape(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        if c.is_ascii_alphanumeric() || "@*_+-./".contains(c) {
+            escaped.push(c);
+        } else if (c as u32) < 256 {
+            escaped.push_str(&format!("%{:02X}", c as u32));
+        } else {
+            let mut units = [0; 2];
+            for unit in c.encode_utf16(&mut units) {
+                escaped.push_str(&format!("%u{:04X}", unit));
+            }
+        }
+    }
+    escaped
+}
+
+/// Unescape text the same way as javascript's `unescape`
+fn unescape(text: &str) -> Result<String> {
+    let mut units = Vec::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            units.push(c as u16);
+            continue;
+        }
+        let rest = chars.as_str();
+        let (hex, len) = match rest.strip_prefix('u') {
+            Some(rest) => (rest.get(..4), 5),
+            None => (rest.get(..2), 2),
+        };
+        match hex.and_then(|hex| u16::from_str_radix(hex, 16).ok()) {
+            Some(unit) => {
+                units.push(unit);
+                chars = rest[len..].chars();
+            }
+            None => units.push('%' as u16),
+        }
+    }
+    Ok(String::from_utf16(&units)?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Action, Ai, Bag, Evaluation, SimpleAi};
+
+    #[test]
+    fn test_fumen() {
+        let fumen = Fumen::decode("v115@vhAAgH").unwrap();
+        assert_eq!(fumen.pages, vec![FumenPage::new(Board::new())]);
+        assert_eq!(fumen.encode().unwrap(), "v115@vhAAgH");
+
+        // I piece at the bottom left
+        let fumen = Fumen::decode("https://fumen.zui.jp/?v115@vhAxOJ").unwrap();
+        let mut piece = Piece::from_piece_type(PieceType::I);
+        while piece.shift_left(&Board::new()) {}
+        piece.soft_drop(&Board::new());
+        assert_eq!(fumen.pages[0].piece, Some(piece));
+        assert_eq!(fumen.encode().unwrap(), "v115@vhAxOJ");
+
+        // Every rotation of every piece
+        for piece_type in PieceType::ALL {
+            for rotation in 0..4 {
+                let piece = piece_from_center(piece_type, rotation, 4, 10).unwrap();
+                assert_eq!(piece_center(&piece).unwrap(), (rotation, 4, 10));
+            }
+        }
+
+        // A sequence of placements with the queue in the comments
+        let mut bag = Bag::new_rng7(3);
+        let mut game = Game::from_bag(&mut bag);
+        game.board.add_garbage(2, 3);
+        let mut ai = SimpleAi::new();
+        let mut fumen = Fumen::new();
+        let mut games = Vec::new();
+        for _ in 0..10 {
+            let Evaluation::Success { actions, .. } = ai.evaluate(&game) else {
+                panic!("evaluation failed");
+            };
+            let mut next = game;
+            for &action in actions.iter() {
+                if action == Action::HardDrop {
+                    next.active.soft_drop(&next.board);
+                    fumen.push_game(&game, Some(next.active));
+                }
+                next.apply(action);
+            }
+            games.push(game);
+            game = next;
+        }
+        fumen.pages[3].garbage = 0b1111011111;
+        fumen.pages[3].rise = true;
+        for page in fumen.pages[1..].iter_mut() {
+            page.comment = None;
+        }
+        fumen.pages[6].comment = Some(String::from("100% done, 完了"));
+
+        let text = fumen.encode().unwrap();
+        assert_eq!(Fumen::decode(&text).unwrap(), fumen);
+        let decoded = Fumen::decode(&text).unwrap().games().unwrap();
+        assert_eq!(decoded.len(), games.len());
+        for (decoded, game) in decoded.iter().zip(games.iter()) {
+            assert_eq!(decoded.board, game.board);
+            assert_eq!(decoded.active.piece_type, game.active.piece_type);
+            assert_eq!(decoded.hold, game.hold);
+            assert_eq!(decoded.queue, game.queue);
+        }
+    }
+}
@@ -0,0 +1,294 @@
+use crate::{
+    ai::play_piece,
+    model::{Bag, Game, GarbageQueue, SpinKind, PIECE_QUEUE_MAX_LEN},
+    Ai,
+};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::{fmt::Write, time::Duration};
+
+/// Garbage sent to the Ai during a benchmark game
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HarnessGarbage {
+    /// Number of pieces between each batch of garbage
+    pub interval: u32,
+    /// Number of lines in each batch of garbage
+    pub lines: u8,
+    /// Number of locks before received garbage is able to rise
+    pub delay: u8,
+    /// Probability that the garbage hole changes column between rows
+    pub messiness: f32,
+}
+
+/// Plays an Ai over a list of seeds and collects statistics
+#[derive(Debug, Clone)]
+pub struct Harness {
+    pub seeds: Vec<u64>,
+    /// Maximum number of pieces placed in each game
+    pub max_pieces: u32,
+    pub garbage: Option<HarnessGarbage>,
+    /// Number of upcoming pieces the Ai is shown
+    pub preview_len: usize,
+    pub hold_disabled: bool,
+}
+
+/// Statistics for a single benchmark game
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GameReport {
+    pub seed: u64,
+    pub pieces: u32,
+    pub lines_cleared: u32,
+    pub attack: u32,
+    /// Number of t-spins, indexed by lines cleared
+    pub tspins: [u32; 4],
+    /// Number of mini spins, indexed by lines cleared
+    pub tspin_minis: [u32; 3],
+    pub perfect_clears: u32,
+    /// Total number of holes in the board after each piece
+    pub holes: Vec<u32>,
+    pub max_height: u32,
+    pub top_out: bool,
+    /// The game ended because the Ai failed to evaluate or lock a piece
+    #[serde(default)]
+    pub ai_failed: bool,
+    /// Percentiles of the time taken to evaluate each piece, in microseconds
+    pub latency_p50: u64,
+    pub latency_p90: u64,
+    pub latency_p99: u64,
+}
+
+/// Statistics for a full benchmark run
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HarnessReport {
+    pub games: Vec<GameReport>,
+    /// Fraction of games that ended in a top out
+    pub top_out_rate: f32,
+    /// Percentiles of the time taken to evaluate each piece across all
+    /// games, in microseconds
+    pub latency_p50: u64,
+    pub latency_p90: u64,
+    pub latency_p99: u64,
+}
+
+impl Harness {
+    pub fn new(seeds: Vec<u64>, max_pieces: u32) -> Self {
+        Harness {
+            seeds,
+            max_pieces,
+            garbage: None,
+            preview_len: PIECE_QUEUE_MAX_LEN,
+            hold_disabled: false,
+        }
+    }
+
+    /// Play one game for every seed, a new Ai is created for each game
+    pub fn run<A: Ai>(&self, mut create: impl FnMut() -> A) -> HarnessReport {
+        let mut games = Vec::new();
+        let mut latencies = Vec::new();
+        for &seed in self.seeds.iter() {
+            let mut ai = create();
+            let (report, game_latencies) = self.play(&mut ai, seed);
+            games.push(report);
+            latencies.extend(game_latencies);
+        }
+
+        let top_outs = games.iter().filter(|game| game.top_out).count();
+        let top_out_rate = top_outs as f32 / games.len().max(1) as f32;
+        latencies.sort();
+        HarnessReport {
+            games,
+            top_out_rate,
+            latency_p50: percentile(&latencies, 0.5),
+            latency_p90: percentile(&latencies, 0.9),
+            latency_p99: percentile(&latencies, 0.99),
+        }
+    }
+
+    /// Play a single game with the given seed
+    pub fn run_game(&self, ai: &mut dyn Ai, seed: u64) -> GameReport {
+        self.play(ai, seed).0
+    }
+
+    fn play(&self, ai: &mut dyn Ai, seed: u64) -> (GameReport, Vec<Duration>) {
+        let mut bag = Bag::new_rng7(seed);
+        let mut game = Game::from_bag_with_preview(&mut bag, self.preview_len);
+        game.hold_disabled = self.hold_disabled;
+        let mut garbage = GarbageQueue::new(seed, &game.board);
+        if let Some(config) = self.garbage {
+            garbage.delay = config.delay;
+            garbage.messiness = config.messiness;
+        }
+        let mut report = GameReport {
+            seed,
+            ..Default::default()
+        };
+        let mut latencies = Vec::new();
+
+        while report.pieces < self.max_pieces {
+            let pieces = report.pieces;
+            let batch = self.garbage.filter(|config| {
+                config.interval > 0 && pieces > 0 && pieces.is_multiple_of(config.interval)
+            });
+            if let Some(config) = batch {
+                garbage.receive(config.lines);
+            }
+
+            let Some((lock_info, elapsed)) = play_piece(ai, &mut game, &mut bag) else {
+                report.ai_failed = true;
+                break;
+            };
+            garbage.on_lock(&mut game, &lock_info);
+            latencies.push(elapsed);
+
+            let lines = lock_info.lines_cleared as usize;
+            report.pieces += 1;
+            report.lines_cleared += lines as u32;
+            report.attack += lock_info.attack as u32;
+            match lock_info.spin {
+                SpinKind::None => {}
+                SpinKind::Mini => report.tspin_minis[lines.min(2)] += 1,
+                SpinKind::Full => report.tspins[lines.min(3)] += 1,
+            }
+            if lock_info.perfect_clear {
+                report.perfect_clears += 1;
+            }
+            let holes = game.board.holes().iter().map(|&x| x as u32).sum();
+            report.holes.push(holes);
+            report.max_height = report.max_height.max(game.board.max_height() as u32);
+
+            if lock_info.top_out
+                || game.board.topped_out()
+                || game.board.intersects_with(&game.active)
+            {
+                report.top_out = true;
+                break;
+            }
+        }
+
+        let mut sorted = latencies.clone();
+        sorted.sort();
+        report.latency_p50 = percentile(&sorted, 0.5);
+        report.latency_p90 = percentile(&sorted, 0.9);
+        report.latency_p99 = percentile(&sorted, 0.99);
+        (report, latencies)
+    }
+}
+
+impl HarnessReport {
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn from_json(text: &str) -> Result<Self> {
+        Ok(serde_json::from_str(text)?)
+    }
+
+    /// Write the report as CSV with one row per game. Holes over time are
+    /// summarized by their mean and final value.
+    pub fn to_csv(&self) -> String {
+        let mut text = String::new();
+        writeln!(
+            text,
+            "seed,pieces,lines_cleared,attack,\
+            tspin_zero,tspin_single,tspin_double,tspin_triple,\
+            tspin_mini_zero,tspin_mini_single,tspin_mini_double,\
+            perfect_clears,holes_mean,holes_final,max_height,top_out,\
+            ai_failed,latency_p50,latency_p90,latency_p99"
+        )
+        .unwrap();
+        for game in self.games.iter() {
+            let holes_mean = game.holes.iter().sum::<u32>() as f32 / game.holes.len().max(1) as f32;
+            let holes_final = game.holes.last().copied().unwrap_or(0);
+            let [t0, t1, t2, t3] = game.tspins;
+            let [m0, m1, m2] = game.tspin_minis;
+            writeln!(
+                text,
+                "{},{},{},{},{t0},{t1},{t2},{t3},{m0},{m1},{m2},{},{holes_mean:.2},{holes_final},{},{},{},{},{},{}",
+                game.seed,
+                game.pieces,
+                game.lines_cleared,
+                game.attack,
+                game.perfect_clears,
+                game.max_height,
+                game.top_out,
+                game.ai_failed,
+                game.latency_p50,
+                game.latency_p90,
+                game.latency_p99,
+            )
+            .unwrap();
+        }
+        text
+    }
+}
+
+/// Nearest-rank percentile of sorted durations, in microseconds
+fn percentile(sorted: &[Duration], p: f32) -> u64 {
+    if sorted.is_empty() {
+        return 0;
+    }
+    let index = ((sorted.len() - 1) as f32 * p).round() as usize;
+    sorted[index].as_micros() as u64
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Evaluation, SimpleAi};
+
+    struct FailAi;
+
+    impl Ai for FailAi {
+        fn evaluate(&mut self, _game: &Game) -> Evaluation {
+            Evaluation::Fail {
+                message: "always fails".to_string(),
+            }
+        }
+    }
+
+    #[test]
+    fn test_harness() {
+        let mut harness = Harness::new(vec![0, 1], 30);
+        harness.garbage = Some(HarnessGarbage {
+            interval: 10,
+            lines: 2,
+            delay: 0,
+            messiness: 0.,
+        });
+        let report = harness.run(SimpleAi::new);
+        assert_eq!(report.games.len(), 2);
+        for game in report.games.iter() {
+            assert!(game.top_out || game.pieces == 30);
+            assert!(!game.ai_failed);
+            assert_eq!(game.holes.len(), game.pieces as usize);
+            assert!(game.latency_p50 <= game.latency_p99);
+        }
+
+        let json = report.to_json().unwrap();
+        assert_eq!(HarnessReport::from_json(&json).unwrap(), report);
+        assert_eq!(report.to_csv().lines().count(), 3);
+
+        // The first batch arrives after `interval` pieces, not before the
+        // first piece
+        let mut first = harness.clone();
+        first.max_pieces = 1;
+        first.garbage = Some(HarnessGarbage {
+            lines: 8,
+            ..harness.garbage.unwrap()
+        });
+        for game in first.run(SimpleAi::new).games.iter() {
+            assert!(game.max_height <= 4);
+        }
+
+        // An Ai failure is not a top out
+        let report = harness.run(|| FailAi);
+        for game in report.games.iter() {
+            assert!(game.ai_failed && !game.top_out);
+            assert_eq!(game.pieces, 0);
+        }
+        assert_eq!(report.top_out_rate, 0.);
+    }
+}
@@ -0,0 +1,226 @@
+use crate::model::{Action, ActionInfo, Bag, Game, PieceType};
+use serde::{Deserialize, Serialize};
+
+/// An action applied to the game and its result
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub action: Action,
+    pub info: ActionInfo,
+}
+
+/// The game at the start of a piece
+#[derive(Debug, Clone, Copy)]
+struct Snapshot {
+    game: Game,
+    /// Number of pieces dealt from the bag into the queue
+    dealt: usize,
+    /// Number of entries applied
+    index: usize,
+}
+
+/// Records every action applied to a game, and can undo or redo to any
+/// piece boundary. Pieces dealt from the bag are kept, so that undoing and
+/// playing again deals the same pieces.
+#[derive(Debug, Clone)]
+pub struct GameHistory {
+    bag: Bag,
+    /// Every piece drawn from the bag so far
+    pieces: Vec<PieceType>,
+    /// Entries of the whole timeline, including ones that can be redone
+    entries: Vec<HistoryEntry>,
+    /// Snapshots at every piece boundary of the timeline
+    snapshots: Vec<Snapshot>,
+    /// Index of the snapshot at or before the current game
+    piece: usize,
+    /// Number of entries applied to the current game
+    index: usize,
+    game: Game,
+    dealt: usize,
+}
+
+impl GameHistory {
+    /// Start recording from a game, the queue is refilled from `bag`
+    pub fn new(game: Game, bag: Bag) -> Self {
+        let mut history = GameHistory {
+            bag,
+            pieces: Vec::new(),
+            entries: Vec::new(),
+            snapshots: Vec::new(),
+            piece: 0,
+            index: 0,
+            game,
+            dealt: 0,
+        };
+        history.reset(game);
+        history
+    }
+
+    /// The game after the actions applied so far
+    pub fn game(&self) -> &Game {
+        &self.game
+    }
+
+    /// The entries applied to reach the current game
+    pub fn entries(&self) -> &[HistoryEntry] {
+        &self.entries[..self.index]
+    }
+
+    /// Number of pieces placed to reach the current game
+    pub fn piece(&self) -> usize {
+        self.piece
+    }
+
+    /// Number of pieces placed in the whole timeline, including pieces that
+    /// can be redone
+    pub fn piece_count(&self) -> usize {
+        self.snapshots.len() - 1
+    }
+
+    /// The game at the start of a piece in the timeline
+    pub fn game_at(&self, piece: usize) -> Option<&Game> {
+        self.snapshots.get(piece).map(|snapshot| &snapshot.game)
+    }
+
+    /// Apply an action to the game and record it. Any pieces that could be
+    /// redone are discarded.
+    pub fn apply(&mut self, action: Action) -> ActionInfo {
+        self.entries.truncate(self.index);
+        self.snapshots.truncate(self.piece + 1);
+
+        let info = self.game.apply(action);
+        self.refill_queue();
+        self.entries.push(HistoryEntry { action, info });
+        self.index += 1;
+        if let ActionInfo::Lock(_) = info {
+            self.piece += 1;
+            self.snapshots.push(Snapshot {
+                game: self.game,
+                dealt: self.dealt,
+                index: self.index,
+            });
+        }
+        info
+    }
+
+    /// The same as `Game::refill_queue`, dealing from the pieces log
+    fn refill_queue(&mut self) {
+        if self.game.active_unknown {
+            let piece = self.deal();
+            self.game.deal_active(piece);
+        }
+        while self.game.queue.len() < self.game.preview_len as usize {
+            let piece = self.deal();
+            self.game.queue.enqueue(piece);
+        }
+    }
+
+    fn deal(&mut self) -> PieceType {
+        if self.dealt == self.pieces.len() {
+            self.pieces.push(self.bag.next());
+        }
+        self.dealt += 1;
+        self.pieces[self.dealt - 1]
+    }
+
+    /// Move to the start of a piece in the timeline, returns false if the
+    /// piece doesn't exist
+    pub fn goto(&mut self, piece: usize) -> bool {
+        let Some(&snapshot) = self.snapshots.get(piece) else {
+            return false;
+        };
+        self.piece = piece;
+        self.index = snapshot.index;
+        self.game = snapshot.game;
+        self.dealt = snapshot.dealt;
+        true
+    }
+
+    /// Go back to the start of the current piece, or to the start of the
+    /// previous piece if no actions have been applied to the current one
+    pub fn undo(&mut self) -> bool {
+        if self.index > self.snapshots[self.piece].index {
+            self.goto(self.piece)
+        } else {
+            self.piece > 0 && self.goto(self.piece - 1)
+        }
+    }
+
+    /// Go forward to the start of the next piece
+    pub fn redo(&mut self) -> bool {
+        self.goto(self.piece + 1)
+    }
+
+    /// Create a new history starting from the current game, without any
+    /// pieces that could be redone. Both histories deal the same pieces.
+    pub fn fork(&self) -> Self {
+        let mut history = self.clone();
+        history.entries.truncate(history.index);
+        history.snapshots.truncate(history.piece + 1);
+        history
+    }
+
+    /// Clear the history and continue from a game that was changed outside
+    /// of `apply`, such as by receiving garbage. The game's queue must have
+    /// been dealt by this history.
+    pub fn reset(&mut self, game: Game) {
+        self.game = game;
+        self.refill_queue();
+        self.entries.clear();
+        self.snapshots = vec![Snapshot {
+            game: self.game,
+            dealt: self.dealt,
+            index: 0,
+        }];
+        self.piece = 0;
+        self.index = 0;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_game_history() {
+        let mut bag = Bag::new_rng7(0);
+        let game = Game::from_bag_with_preview(&mut bag, 5);
+        let mut history = GameHistory::new(game, bag);
+        assert!(!history.undo());
+
+        let actions = [Action::ShiftLeft, Action::HardDrop, Action::Hold];
+        for &action in actions.iter().cycle().take(15) {
+            history.apply(action);
+        }
+        let end = *history.game();
+        assert_eq!(history.piece(), 5);
+        assert_eq!(history.entries().len(), 15);
+
+        // Undo the unfinished piece, then two more
+        assert!(history.undo());
+        assert_eq!(history.entries().len(), 14);
+        assert!(history.undo() && history.undo());
+        assert_eq!(history.piece(), 3);
+        assert_eq!(history.game(), history.game_at(3).unwrap());
+        assert!(history.redo() && history.redo());
+        assert!(!history.redo());
+        history.apply(Action::Hold);
+        assert_eq!(*history.game(), end);
+
+        // Playing differently deals the same pieces and drops the redo
+        assert!(history.goto(1));
+        let mut fork = history.fork();
+        history.apply(Action::HardDrop);
+        assert_eq!(history.piece_count(), 2);
+        assert_eq!(fork.piece_count(), 1);
+        fork.apply(Action::HardDrop);
+        assert_eq!(fork.game(), history.game());
+
+        let mut bag = Bag::new_rng7(0);
+        let game = Game::from_bag_with_preview(&mut bag, 5);
+        let mut straight = GameHistory::new(game, bag);
+        for action in [Action::ShiftLeft, Action::HardDrop, Action::HardDrop] {
+            straight.apply(action);
+        }
+        assert_eq!(straight.game(), history.game());
+    }
+}
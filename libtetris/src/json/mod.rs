@@ -0,0 +1,277 @@
+use crate::{Game, PieceType, BOARD_HEIGHT, BOARD_MAX_HEIGHT, BOARD_MAX_WIDTH, BOARD_WIDTH};
+use anyhow::{anyhow, bail, Result};
+use serde_json::{Map, Value};
+
+/// Version of the game json schema written by `GameJson`
+pub const GAME_JSON_VERSION: u64 = 2;
+
+/// The color of a tile on the board
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Tile {
+    #[default]
+    Empty,
+    Piece(PieceType),
+    Garbage,
+}
+
+impl Tile {
+    /// `' '` for empty tiles, the piece letter or `'G'` for garbage
+    pub fn to_char(self) -> char {
+        match self {
+            Tile::Empty => ' ',
+            Tile::Piece(PieceType::O) => 'O',
+            Tile::Piece(PieceType::I) => 'I',
+            Tile::Piece(PieceType::T) => 'T',
+            Tile::Piece(PieceType::L) => 'L',
+            Tile::Piece(PieceType::J) => 'J',
+            Tile::Piece(PieceType::S) => 'S',
+            Tile::Piece(PieceType::Z) => 'Z',
+            Tile::Garbage => 'G',
+        }
+    }
+
+    pub fn from_char(c: char) -> Result<Self> {
+        match c {
+            ' ' => Ok(Tile::Empty),
+            'O' => Ok(Tile::Piece(PieceType::O)),
+            'I' => Ok(Tile::Piece(PieceType::I)),
+            'T' => Ok(Tile::Piece(PieceType::T)),
+            'L' => Ok(Tile::Piece(PieceType::L)),
+            'J' => Ok(Tile::Piece(PieceType::J)),
+            'S' => Ok(Tile::Piece(PieceType::S)),
+            'Z' => Ok(Tile::Piece(PieceType::Z)),
+            'G' => Ok(Tile::Garbage),
+            c => Err(anyhow!("unknown tile {c:?}")),
+        }
+    }
+}
+
+/// A game together with the color of every tile on its board, read and
+/// written using a versioned json schema.
+///
+/// Version 2 is the same as the json of `Game` with a `"version": 2` field,
+/// except that each tile of `board.matrix` is its color: `' '` when empty,
+/// `'O'`, `'I'`, `'T'`, `'L'`, `'J'`, `'S'`, `'Z'` for pieces and `'G'` for
+/// garbage. The matrix lists `width * height` tiles row by row, starting from
+/// the bottom left.
+///
+/// Version 1 is the older unversioned json of `Game`, any json without a
+/// `version` field is read as version 1. Its tiles are `' '` when empty and
+/// any other character when filled. Older versions of libtetris wrote the
+/// board inverted, using `'G'` for empty tiles. Such boards are recognized
+/// by their top row, which can never be filled, and flipped back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct GameJson {
+    pub game: Game,
+    /// Tile colors indexed by `[x][y]`, any filled tile of the board that is
+    /// `Tile::Empty` is written as garbage
+    pub tiles: [[Tile; BOARD_MAX_HEIGHT]; BOARD_MAX_WIDTH],
+}
+
+impl GameJson {
+    /// Create from a game, every filled tile is garbage
+    pub fn new(game: Game) -> Self {
+        let mut tiles = [[Tile::Empty; BOARD_MAX_HEIGHT]; BOARD_MAX_WIDTH];
+        for (i, column) in tiles.iter_mut().enumerate().take(game.board.width()) {
+            for (j, tile) in column.iter_mut().enumerate().take(game.board.height()) {
+                if game.board.get(i, j) {
+                    *tile = Tile::Garbage;
+                }
+            }
+        }
+        GameJson { game, tiles }
+    }
+
+    /// The color of a tile, always `Tile::Empty` for empty tiles
+    pub fn tile(&self, x: usize, y: usize) -> Tile {
+        match (self.game.board.get(x, y), self.tiles[x][y]) {
+            (false, _) => Tile::Empty,
+            (true, Tile::Empty) => Tile::Garbage,
+            (true, tile) => tile,
+        }
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        let mut value = serde_json::to_value(self.game)?;
+        let board = &self.game.board;
+        let mut matrix = Vec::new();
+        for j in 0..board.height() {
+            for i in 0..board.width() {
+                matrix.push(Value::from(self.tile(i, j).to_char().to_string()));
+            }
+        }
+        let object = as_object(&mut value)?;
+        as_object(object.get_mut("board").unwrap())?.insert("matrix".into(), matrix.into());
+        object.insert("version".into(), GAME_JSON_VERSION.into());
+        Ok(serde_json::to_string(&value)?)
+    }
+
+    /// Read a game written with any version of the schema
+    pub fn from_json(text: &str) -> Result<Self> {
+        let mut value = serde_json::from_str::<Value>(text)?;
+        let object = as_object(&mut value)?;
+        let version = match object.remove("version") {
+            Some(version) => version
+                .as_u64()
+                .ok_or_else(|| anyhow!("expected version to be a number, got {version}"))?,
+            None => 1,
+        };
+        match version {
+            1 => migrate_v1(object)?,
+            GAME_JSON_VERSION => {}
+            x => bail!("unsupported game json version {x}"),
+        }
+
+        let game = serde_json::from_value::<Game>(value.clone())?;
+        let matrix = value["board"]["matrix"]
+            .as_array()
+            .ok_or_else(|| anyhow!("expected board matrix to be an array"))?;
+        let mut json = GameJson::new(game);
+        for j in 0..game.board.height() {
+            for i in 0..game.board.width() {
+                let tile = match &matrix[j * game.board.width() + i] {
+                    Value::String(tile) if tile.chars().count() == 1 => {
+                        Tile::from_char(tile.chars().next().unwrap())
+                    }
+                    tile => Err(anyhow!("expected a tile, got {tile}")),
+                };
+                // Version 1 tiles can be any character
+                json.tiles[i][j] = match (version, tile) {
+                    (_, Ok(tile)) => tile,
+                    (1, Err(_)) => Tile::Garbage,
+                    (_, Err(err)) => return Err(err),
+                };
+            }
+        }
+        Ok(json)
+    }
+}
+
+fn as_object(value: &mut Value) -> Result<&mut Map<String, Value>> {
+    match value {
+        Value::Object(object) => Ok(object),
+        value => Err(anyhow!("expected an object, got {value}")),
+    }
+}
+
+/// Flip boards written inverted by older versions of libtetris
+fn migrate_v1(game: &mut Map<String, Value>) -> Result<()> {
+    let board = as_object(
+        game.get_mut("board")
+            .ok_or_else(|| anyhow!("missing field `board`"))?,
+    )?;
+    let width = board
+        .get("width")
+        .and_then(Value::as_u64)
+        .map_or(BOARD_WIDTH, |x| x as usize);
+    let height = board
+        .get("height")
+        .and_then(Value::as_u64)
+        .map_or(BOARD_HEIGHT, |x| x as usize);
+    let Some(Value::Array(matrix)) = board.get_mut("matrix") else {
+        return Ok(());
+    };
+    if width == 0 || height == 0 || matrix.len() < width * height {
+        return Ok(());
+    }
+    let is = |tile: &Value, c: &str| tile.as_str() == Some(c);
+    let inverted = matrix.iter().all(|tile| is(tile, " ") || is(tile, "G"))
+        && matrix[(height - 1) * width..height * width]
+            .iter()
+            .all(|tile| is(tile, "G"));
+    if inverted {
+        for tile in matrix.iter_mut() {
+            *tile = Value::from(if is(tile, "G") { " " } else { "G" });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Bag, Board};
+    use proptest::prelude::*;
+
+    fn arb_game() -> impl Strategy<Value = (Game, Vec<u16>)> {
+        (
+            any::<u64>(),
+            4..=BOARD_MAX_WIDTH,
+            1..=(BOARD_MAX_HEIGHT - 4),
+            any::<bool>(),
+        )
+            .prop_flat_map(|(seed, width, visible_height, hold)| {
+                let rows = prop::collection::vec(any::<u16>(), 0..=visible_height);
+                let colors = prop::collection::vec(0..9u16, width * visible_height);
+                (Just((seed, width, visible_height, hold)), rows, colors)
+            })
+            .prop_map(|((seed, width, visible_height, hold), rows, colors)| {
+                let mut board =
+                    Board::with_size(width, visible_height + 4, visible_height).unwrap();
+                for (j, row) in rows.into_iter().enumerate() {
+                    board.matrix[j] = (row as u32 & ((1 << width) - 1)) as u16;
+                }
+                let mut game = Game::from_bag(&mut Bag::new_rng7(seed));
+                if hold {
+                    game.swap_hold();
+                }
+                game.set_board(board);
+                (game, colors)
+            })
+    }
+
+    fn colored(game: Game, colors: &[u16]) -> GameJson {
+        let mut json = GameJson::new(game);
+        for (i, &color) in colors.iter().enumerate() {
+            let (x, y) = (i % game.board.width(), i / game.board.width());
+            json.tiles[x][y] = match color {
+                0..7 => Tile::Piece(PieceType::from_u8(color as u8).unwrap()),
+                7 => Tile::Garbage,
+                _ => Tile::Empty,
+            };
+        }
+        json
+    }
+
+    proptest! {
+        #[test]
+        fn test_game_json((game, colors) in arb_game()) {
+            let text = serde_json::to_string(&game).unwrap();
+            prop_assert_eq!(serde_json::from_str::<Game>(&text).unwrap(), game);
+
+            let json = colored(game, &colors);
+            let decoded = GameJson::from_json(&json.to_json().unwrap()).unwrap();
+            prop_assert_eq!(decoded.game, game);
+            for x in 0..game.board.width() {
+                for y in 0..game.board.height() {
+                    prop_assert_eq!(decoded.tile(x, y), json.tile(x, y));
+                }
+            }
+
+            // Unversioned json, both as written by the web ui and inverted
+            prop_assert_eq!(GameJson::from_json(&text).unwrap().game, game);
+            let mut value = serde_json::to_value(game).unwrap();
+            for tile in value["board"]["matrix"].as_array_mut().unwrap() {
+                *tile = Value::from(if tile == " " { "G" } else { " " });
+            }
+            prop_assert_eq!(GameJson::from_json(&value.to_string()).unwrap().game, game);
+        }
+    }
+
+    #[test]
+    fn test_game_json_version() {
+        let game = Game::from_bag(&mut Bag::new_rng7(0));
+        let mut value = serde_json::to_value(game).unwrap();
+        value["version"] = 3.into();
+        assert!(GameJson::from_json(&value.to_string()).is_err());
+        value["version"] = 2.into();
+        value["board"]["matrix"][0] = "X".into();
+        assert!(GameJson::from_json(&value.to_string()).is_err());
+        value.as_object_mut().unwrap().remove("version");
+        assert!(GameJson::from_json(&value.to_string())
+            .unwrap()
+            .game
+            .board
+            .get(0, 0));
+    }
+}
@@ -1,8 +1,23 @@
 mod ai;
+mod features;
+mod fumen;
+mod harness;
+mod history;
+mod json;
 mod model;
 mod pack;
+mod realtime;
+mod replay;
 mod serde;
+mod versus;
 
 pub use ai::*;
+pub use fumen::*;
+pub use harness::*;
+pub use history::*;
+pub use json::*;
 pub use model::*;
 pub use pack::*;
+pub use realtime::*;
+pub use replay::*;
+pub use versus::*;
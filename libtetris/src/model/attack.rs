@@ -0,0 +1,129 @@
+use crate::{LockInfo, SpinKind};
+use serde::{Deserialize, Serialize};
+
+/// Number of garbage lines sent for each kind of line clear
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AttackTable {
+    /// Attack for a normal clear, indexed by lines cleared
+    pub clear: [u8; 5],
+    /// Attack for a t-spin clear, indexed by lines cleared
+    pub tspin: [u8; 4],
+    /// Attack for a mini spin clear, indexed by lines cleared
+    pub tspin_mini: [u8; 3],
+    /// Bonus attack for a back-to-back clear
+    pub back_to_back: u8,
+    /// Bonus attack indexed by combo count, the last entry is used for any
+    /// longer combos
+    pub combo: [u8; 12],
+    /// If set, clears that already send attack are multiplied by
+    /// (1 + 0.25 * combo) instead of receiving the combo bonus
+    pub combo_multiplier: bool,
+    /// Bonus attack for a perfect clear
+    pub perfect_clear: u8,
+}
+
+impl AttackTable {
+    /// Attack table used by most guideline games
+    pub const GUIDELINE: AttackTable = AttackTable {
+        clear: [0, 0, 1, 2, 4],
+        tspin: [0, 2, 4, 6],
+        tspin_mini: [0, 0, 1],
+        back_to_back: 1,
+        combo: [0, 1, 1, 2, 2, 3, 3, 4, 4, 4, 5, 5],
+        combo_multiplier: false,
+        perfect_clear: 10,
+    };
+
+    /// Attack table used by Tetrio
+    pub const TETRIO: AttackTable = AttackTable {
+        clear: [0, 0, 1, 2, 4],
+        tspin: [0, 2, 4, 6],
+        tspin_mini: [0, 0, 1],
+        back_to_back: 1,
+        combo: [0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 2, 2],
+        combo_multiplier: true,
+        perfect_clear: 10,
+    };
+
+    /// Compute the attack sent by a lock. Expects the combo and back-to-back
+    /// fields of the lock info to already be filled in.
+    pub fn attack(&self, lock_info: &LockInfo) -> u8 {
+        let lines = lock_info.lines_cleared as usize;
+        if lines == 0 {
+            return 0;
+        }
+
+        let table: &[u8] = match lock_info.spin {
+            SpinKind::None => &self.clear,
+            SpinKind::Mini => &self.tspin_mini,
+            SpinKind::Full => &self.tspin,
+        };
+        let mut attack = table.get(lines).copied().unwrap_or(0) as u32;
+        if lock_info.back_to_back {
+            attack += self.back_to_back as u32;
+        }
+
+        // The first clear of a chain does not count as a combo
+        let combo = (lock_info.combo as usize).saturating_sub(1);
+        if self.combo_multiplier && attack > 0 {
+            attack = attack * (4 + combo as u32) / 4;
+        } else {
+            attack += self.combo[combo.min(self.combo.len() - 1)] as u32;
+        }
+
+        if lock_info.perfect_clear {
+            attack += self.perfect_clear as u32;
+        }
+        attack.min(u8::MAX as u32) as u8
+    }
+}
+
+impl Default for AttackTable {
+    fn default() -> Self {
+        AttackTable::GUIDELINE
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn lock_info(lines_cleared: u8, spin: SpinKind, combo: u8, back_to_back: bool) -> LockInfo {
+        LockInfo {
+            top_out: false,
+            lines_cleared,
+            spin,
+            perfect_clear: false,
+            combo,
+            back_to_back,
+            attack: 0,
+        }
+    }
+
+    #[test]
+    fn test_attack() {
+        let table = AttackTable::GUIDELINE;
+        assert_eq!(table.attack(&lock_info(0, SpinKind::Full, 5, true)), 0);
+        assert_eq!(table.attack(&lock_info(1, SpinKind::None, 1, false)), 0);
+        assert_eq!(table.attack(&lock_info(4, SpinKind::None, 1, false)), 4);
+        assert_eq!(table.attack(&lock_info(2, SpinKind::Full, 1, true)), 5);
+        assert_eq!(table.attack(&lock_info(2, SpinKind::Mini, 1, false)), 1);
+        // The third clear in a row is a combo of 2
+        assert_eq!(table.attack(&lock_info(1, SpinKind::None, 3, false)), 1);
+        assert_eq!(table.attack(&lock_info(3, SpinKind::None, 20, false)), 7);
+        let perfect_clear = LockInfo {
+            perfect_clear: true,
+            ..lock_info(4, SpinKind::None, 1, false)
+        };
+        assert_eq!(table.attack(&perfect_clear), 14);
+
+        // Tetrio multiplies attack by the combo, and only gives the combo
+        // bonus to clears that send nothing
+        let table = AttackTable::TETRIO;
+        assert_eq!(table.attack(&lock_info(4, SpinKind::None, 3, false)), 6);
+        assert_eq!(table.attack(&lock_info(2, SpinKind::Full, 5, true)), 10);
+        assert_eq!(table.attack(&lock_info(1, SpinKind::None, 3, false)), 1);
+        assert_eq!(table.attack(&lock_info(1, SpinKind::None, 1, false)), 0);
+    }
+}
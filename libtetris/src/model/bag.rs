@@ -1,56 +1,355 @@
-use crate::model::PieceType;
-use rand::seq::SliceRandom;
+use super::distribution::{bag_slots, one_hot, tgm_slots};
+use crate::model::{PieceDistribution, PieceType};
+use rand::{seq::SliceRandom, Rng};
 use rand_core::SeedableRng;
 use rand_xorshift::XorShiftRng;
+use serde::{Deserialize, Serialize};
 use std::collections::VecDeque;
 
+/// The parameters a bag was created with, used to recreate the bag
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", tag = "kind")]
+pub enum BagKind {
+    Fixed { sequence: Vec<PieceType> },
+    Rng7 { seed: u64 },
+    Rng14 { seed: u64 },
+    Random { seed: u64 },
+    Tgm { seed: u64 },
+    Weighted { seed: u64, weights: [u32; 7] },
+}
+
+impl Default for BagKind {
+    /// A 7-bag, the guideline randomizer
+    fn default() -> Self {
+        BagKind::Rng7 { seed: 0 }
+    }
+}
+
+impl BagKind {
+    /// Create a new bag of this kind
+    pub fn bag(&self) -> Bag {
+        match self {
+            BagKind::Fixed { sequence } => Bag::new_fixed(sequence),
+            BagKind::Rng7 { seed } => Bag::new_rng7(*seed),
+            BagKind::Rng14 { seed } => Bag::new_rng14(*seed),
+            BagKind::Random { seed } => Bag::new_random(*seed),
+            BagKind::Tgm { seed } => Bag::new_tgm(*seed),
+            BagKind::Weighted { seed, weights } => Bag::new_weighted(*seed, *weights),
+        }
+    }
+}
+
+/// Generates the sequence of pieces dealt to a game
+pub trait Randomizer {
+    /// Generate the next piece
+    fn next(&mut self) -> PieceType;
+}
+
+/// Repeats a fixed sequence of pieces
+#[derive(Debug, Clone)]
+pub struct FixedRandomizer {
+    sequence: Vec<PieceType>,
+    index: usize,
+}
+
+impl FixedRandomizer {
+    pub fn new(sequence: &[PieceType]) -> Self {
+        assert!(!sequence.is_empty(), "sequence must not be empty");
+        FixedRandomizer {
+            sequence: sequence.to_vec(),
+            index: 0,
+        }
+    }
+}
+
+impl Randomizer for FixedRandomizer {
+    fn next(&mut self) -> PieceType {
+        let piece_type = self.sequence[self.index];
+        self.index = (self.index + 1) % self.sequence.len();
+        piece_type
+    }
+}
+
+/// Deals shuffled bags holding a number of copies of every piece, one copy
+/// gives the guideline 7-bag and two copies give a 14-bag
+#[derive(Debug, Clone)]
+pub struct BagRandomizer {
+    rng: XorShiftRng,
+    copies: usize,
+    queue: VecDeque<PieceType>,
+}
+
+impl BagRandomizer {
+    pub fn new(seed: u64, copies: usize) -> Self {
+        assert!(copies > 0, "bag must hold at least one copy of each piece");
+        BagRandomizer {
+            rng: XorShiftRng::seed_from_u64(seed),
+            copies,
+            queue: VecDeque::new(),
+        }
+    }
+}
+
+impl Randomizer for BagRandomizer {
+    fn next(&mut self) -> PieceType {
+        if self.queue.is_empty() {
+            let mut pieces = Vec::with_capacity(7 * self.copies);
+            for _ in 0..self.copies {
+                pieces.extend(PieceType::ALL);
+            }
+            pieces.shuffle(&mut self.rng);
+            self.queue.extend(pieces);
+        }
+        self.queue.pop_front().unwrap()
+    }
+}
+
+/// Picks every piece independently, with a weight for each piece type
+/// indexed by `PieceType::to_u8`
+#[derive(Debug, Clone)]
+pub struct WeightedRandomizer {
+    rng: XorShiftRng,
+    weights: [u32; 7],
+}
+
+impl WeightedRandomizer {
+    pub fn new(seed: u64, weights: [u32; 7]) -> Self {
+        assert!(
+            weights.iter().any(|&weight| weight > 0),
+            "at least one weight must be positive"
+        );
+        WeightedRandomizer {
+            rng: XorShiftRng::seed_from_u64(seed),
+            weights,
+        }
+    }
+
+    /// Every piece is equally likely
+    pub fn uniform(seed: u64) -> Self {
+        WeightedRandomizer::new(seed, [1; 7])
+    }
+}
+
+impl Randomizer for WeightedRandomizer {
+    fn next(&mut self) -> PieceType {
+        let total = self.weights.iter().map(|&weight| weight as u64).sum();
+        let mut roll = self.rng.random_range(0..total);
+        for (piece_type, &weight) in PieceType::ALL.iter().zip(self.weights.iter()) {
+            if roll < weight as u64 {
+                return *piece_type;
+            }
+            roll -= weight as u64;
+        }
+        unreachable!()
+    }
+}
+
+/// The randomizer used by Tetris The Grand Master 3. Pieces are drawn from
+/// a pool of 35 with up to 6 rolls, rerolling pieces that are in the history
+/// of the last 4 pieces. Rerolled and dealt pieces in the pool are replaced
+/// by the piece that hasn't been dealt for the longest.
+#[derive(Debug, Clone)]
+pub struct TgmRandomizer {
+    rng: XorShiftRng,
+    pool: [PieceType; 35],
+    history: [PieceType; 4],
+    /// Number of pieces dealt since each piece type was last dealt
+    droughts: [u32; 7],
+    first: bool,
+}
+
+impl TgmRandomizer {
+    pub fn new(seed: u64) -> Self {
+        let mut pool = [PieceType::O; 35];
+        for (i, piece_type) in pool.iter_mut().enumerate() {
+            *piece_type = PieceType::ALL[i % 7];
+        }
+        TgmRandomizer {
+            rng: XorShiftRng::seed_from_u64(seed),
+            pool,
+            history: [PieceType::Z, PieceType::S, PieceType::Z, PieceType::S],
+            droughts: [0; 7],
+            first: true,
+        }
+    }
+
+    fn most_droughted(&self) -> PieceType {
+        let mut best = 0;
+        for i in 1..7 {
+            if self.droughts[i] > self.droughts[best] {
+                best = i;
+            }
+        }
+        PieceType::ALL[best]
+    }
+}
+
+impl Randomizer for TgmRandomizer {
+    fn next(&mut self) -> PieceType {
+        let piece_type = if self.first {
+            // The first piece is never an S, Z or O
+            self.first = false;
+            let first = [PieceType::I, PieceType::T, PieceType::L, PieceType::J];
+            first[self.rng.random_range(0..4)]
+        } else {
+            let mut index = 0;
+            for roll in 0..6 {
+                index = self.rng.random_range(0..35);
+                if !self.history.contains(&self.pool[index]) || roll == 5 {
+                    break;
+                }
+                self.pool[index] = self.most_droughted();
+            }
+            let piece_type = self.pool[index];
+            for drought in self.droughts.iter_mut() {
+                *drought += 1;
+            }
+            self.droughts[piece_type.to_u8() as usize] = 0;
+            self.pool[index] = self.most_droughted();
+            piece_type
+        };
+        self.history.rotate_left(1);
+        self.history[3] = piece_type;
+        piece_type
+    }
+}
+
 /// A bag of Tetris pieces that can be pulled from
 #[derive(Debug, Clone)]
 pub enum Bag {
-    Fixed {
-        sequence: Vec<PieceType>,
-        queue: VecDeque<PieceType>,
-    },
-    Rng7 {
-        rng: XorShiftRng,
-        queue: VecDeque<PieceType>,
-    },
+    Fixed(FixedRandomizer),
+    Shuffled(BagRandomizer),
+    Weighted(WeightedRandomizer),
+    Tgm(TgmRandomizer),
 }
 
 impl Bag {
     /// Generate a new fixed sequence bag
     pub fn new_fixed(sequence: &[PieceType]) -> Self {
-        Bag::Fixed {
-            sequence: sequence.to_vec(),
-            queue: VecDeque::new(),
-        }
+        Bag::Fixed(FixedRandomizer::new(sequence))
     }
 
     /// Generate a new 7-bag
     pub fn new_rng7(seed: u64) -> Self {
-        Bag::Rng7 {
-            rng: XorShiftRng::seed_from_u64(seed),
-            queue: VecDeque::new(),
-        }
+        Bag::Shuffled(BagRandomizer::new(seed, 1))
+    }
+
+    /// Generate a new 14-bag, holding two of every piece
+    pub fn new_rng14(seed: u64) -> Self {
+        Bag::Shuffled(BagRandomizer::new(seed, 2))
+    }
+
+    /// Generate a memoryless bag where every piece is equally likely
+    pub fn new_random(seed: u64) -> Self {
+        Bag::Weighted(WeightedRandomizer::uniform(seed))
+    }
+
+    /// Generate a memoryless bag with a weight for each piece type
+    pub fn new_weighted(seed: u64, weights: [u32; 7]) -> Self {
+        Bag::Weighted(WeightedRandomizer::new(seed, weights))
+    }
+
+    /// Generate a bag using the TGM3 randomizer
+    pub fn new_tgm(seed: u64) -> Self {
+        Bag::Tgm(TgmRandomizer::new(seed))
     }
 
     /// Dequeue the next piece from the bag
     pub fn next(&mut self) -> PieceType {
-        match self {
-            Bag::Fixed { sequence, queue } => {
-                if queue.is_empty() {
-                    queue.extend(sequence.iter().copied());
+        Randomizer::next(self)
+    }
+
+    /// The distribution of each of the next `count` pieces as seen by a
+    /// player who knows the state of the bag, such as how far into the
+    /// current 7-bag it is, but not the outcome of its random rolls
+    pub fn next_pieces(&self, count: usize) -> Vec<PieceDistribution> {
+        let slots = match self {
+            Bag::Fixed(randomizer) => (0..count)
+                .map(|i| {
+                    let piece_type =
+                        randomizer.sequence[(randomizer.index + i) % randomizer.sequence.len()];
+                    one_hot(piece_type)
+                })
+                .collect(),
+            Bag::Shuffled(randomizer) => {
+                let mut remaining = [0; 7];
+                for piece_type in randomizer.queue.iter() {
+                    remaining[piece_type.to_u8() as usize] += 1;
                 }
-                queue.pop_front().unwrap()
+                bag_slots(remaining, randomizer.queue.len(), count)
             }
-            Bag::Rng7 { rng, queue } => {
-                if queue.is_empty() {
-                    let mut arr = PieceType::ALL;
-                    arr.shuffle(rng);
-                    queue.extend(arr);
-                }
-                queue.pop_front().unwrap()
+            Bag::Weighted(randomizer) => vec![randomizer.weights.map(|x| x as f64); count],
+            Bag::Tgm(randomizer) => {
+                tgm_slots(randomizer.history.map(Some), randomizer.first, count)
             }
+        };
+        slots
+            .into_iter()
+            .map(PieceDistribution::from_weights)
+            .collect()
+    }
+}
+
+impl Randomizer for Bag {
+    fn next(&mut self) -> PieceType {
+        match self {
+            Bag::Fixed(randomizer) => randomizer.next(),
+            Bag::Shuffled(randomizer) => randomizer.next(),
+            Bag::Weighted(randomizer) => randomizer.next(),
+            Bag::Tgm(randomizer) => randomizer.next(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn counts(bag: &mut Bag, len: usize) -> [usize; 7] {
+        let mut counts = [0; 7];
+        for _ in 0..len {
+            counts[bag.next().to_u8() as usize] += 1;
         }
+        counts
+    }
+
+    #[test]
+    fn test_randomizers() {
+        let mut bag = Bag::new_fixed(&[PieceType::T, PieceType::I]);
+        assert_eq!(counts(&mut bag, 5), [0, 2, 3, 0, 0, 0, 0]);
+
+        let mut bag = Bag::new_rng7(0);
+        for _ in 0..10 {
+            assert_eq!(counts(&mut bag, 7), [1; 7]);
+        }
+        let mut bag = Bag::new_rng14(0);
+        for _ in 0..10 {
+            assert_eq!(counts(&mut bag, 14), [2; 7]);
+        }
+
+        let counts_random = counts(&mut Bag::new_random(0), 7000);
+        assert!(counts_random
+            .iter()
+            .all(|&count| (800..1200).contains(&count)));
+        let weights = [0, 3, 1, 0, 0, 0, 0];
+        let counts_weighted = counts(&mut Bag::new_weighted(0, weights), 4000);
+        assert_eq!(counts_weighted[0], 0);
+        assert!((2700..3300).contains(&counts_weighted[1]));
+
+        // The TGM randomizer never starts with S, Z or O and rarely repeats
+        // pieces from its history
+        for seed in 0..20 {
+            let first = Bag::new_tgm(seed).next();
+            assert!(![PieceType::S, PieceType::Z, PieceType::O].contains(&first));
+        }
+        let mut bag = Bag::new_tgm(0);
+        let pieces = (0..7000).map(|_| bag.next()).collect::<Vec<_>>();
+        let repeats = pieces.windows(2).filter(|x| x[0] == x[1]).count();
+        assert!(repeats < 100);
+
+        // Bags are deterministic given a seed
+        let kind = BagKind::Tgm { seed: 3 };
+        let (mut a, mut b) = (kind.bag(), kind.bag());
+        assert!((0..100).all(|_| a.next() == b.next()));
     }
 }
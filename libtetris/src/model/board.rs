@@ -1,27 +1,108 @@
 use super::{piece::Piece, PieceType};
-use crate::PieceInfo;
+use anyhow::{bail, Result};
 use serde::{Deserialize, Serialize};
 use std::{
     fmt::{self, Display, Formatter, Write},
     hash::Hash,
+    ops::Deref,
 };
 
-/// Width of the board
+/// Width of the default board
 pub const BOARD_WIDTH: usize = 10;
 
-/// Height of the board
+/// Height of the default board
 pub const BOARD_HEIGHT: usize = 24;
 
-/// Visible height of the board
+/// Visible height of the default board
 /// Any pieces placed above this is considered a top-out
 pub const BOARD_VISIBLE_HEIGHT: usize = 20;
 
+/// Maximum width of any board, each row is stored as a u16
+pub const BOARD_MAX_WIDTH: usize = 16;
+
+/// Maximum height of any board, including the hidden rows above the visible
+/// area
+pub const BOARD_MAX_HEIGHT: usize = 40;
+
+/// The kind of spin performed by a piece before locking
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SpinKind {
+    #[default]
+    None,
+    Mini,
+    Full,
+}
+
+/// Which pieces are able to perform spins
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SpinMode {
+    /// Only T pieces can spin
+    #[default]
+    TSpin,
+    /// T pieces spin as usual, any other piece that is immobile after
+    /// rotating counts as a mini spin
+    AllSpin,
+}
+
 /// Information about the board after locking a piece
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct LockInfo {
     pub top_out: bool,
     pub lines_cleared: u8,
-    pub tspin: bool,
+    pub spin: SpinKind,
+    pub perfect_clear: bool,
+    /// Number of consecutive locks that cleared lines, including this one.
+    /// Only filled in by `Game::lock`
+    pub combo: u8,
+    /// Whether this clear continued a back-to-back chain.
+    /// Only filled in by `Game::lock`
+    pub back_to_back: bool,
+    /// Number of garbage lines sent by this lock.
+    /// Only filled in by `Game::lock`
+    pub attack: u8,
+}
+
+/// A value for each column of a board, such as its height or number of
+/// holes. Derefs to a slice with one entry per column.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ColumnMap {
+    values: [i8; BOARD_MAX_WIDTH],
+    width: u8,
+}
+
+impl ColumnMap {
+    /// Create a map for a board `width` columns wide from the value of each
+    /// column
+    pub(crate) fn from_fn(width: usize, mut f: impl FnMut(usize) -> i8) -> Self {
+        let mut values = [0; BOARD_MAX_WIDTH];
+        for (x, value) in values.iter_mut().enumerate().take(width) {
+            *value = f(x);
+        }
+        ColumnMap {
+            values,
+            width: width as u8,
+        }
+    }
+}
+
+impl Deref for ColumnMap {
+    type Target = [i8];
+
+    fn deref(&self) -> &[i8] {
+        &self.values[..self.width as usize]
+    }
+}
+
+impl IntoIterator for ColumnMap {
+    type Item = i8;
+    type IntoIter = std::iter::Take<std::array::IntoIter<i8, BOARD_MAX_WIDTH>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.values.into_iter().take(self.width as usize)
+    }
 }
 
 /// Represents a rectangular grid of tiles using a bitboard
@@ -29,17 +110,70 @@ pub struct LockInfo {
 #[serde(try_from = "crate::serde::SerializedBoard")]
 #[serde(into = "crate::serde::SerializedBoard")]
 pub struct Board {
-    /// A bitboard representation of the board, the first 10 bits of each u16
-    /// row represents a tile.
-    pub matrix: [u16; BOARD_HEIGHT],
+    /// A bitboard representation of the board, the first `width` bits of
+    /// each u16 row represents a tile. Rows at or above `height` are always
+    /// empty.
+    pub matrix: [u16; BOARD_MAX_HEIGHT],
+    width: u8,
+    height: u8,
+    visible_height: u8,
 }
 
 impl Board {
-    /// Create a new board
+    /// Create a new board with the default 10x24 size
     pub fn new() -> Self {
         Board {
-            matrix: [0; BOARD_HEIGHT],
+            matrix: [0; BOARD_MAX_HEIGHT],
+            width: BOARD_WIDTH as u8,
+            height: BOARD_HEIGHT as u8,
+            visible_height: BOARD_VISIBLE_HEIGHT as u8,
+        }
+    }
+
+    /// Create a new board with a custom size, pieces placed above the visible
+    /// height are considered a top-out
+    pub fn with_size(width: usize, height: usize, visible_height: usize) -> Result<Self> {
+        if !(4..=BOARD_MAX_WIDTH).contains(&width) {
+            bail!("board width must be between 4 and {BOARD_MAX_WIDTH}, got {width}");
+        }
+        if height > BOARD_MAX_HEIGHT {
+            bail!("board height must be at most {BOARD_MAX_HEIGHT}, got {height}");
         }
+        if visible_height == 0 || visible_height + 4 > height {
+            bail!("board needs at least 4 rows above the visible height, got {visible_height}/{height}");
+        }
+        Ok(Board {
+            matrix: [0; BOARD_MAX_HEIGHT],
+            width: width as u8,
+            height: height as u8,
+            visible_height: visible_height as u8,
+        })
+    }
+
+    /// Create a new 10x40 board for guideline-style play, with 20 visible
+    /// rows
+    pub fn guideline() -> Self {
+        Board::with_size(BOARD_WIDTH, BOARD_MAX_HEIGHT, BOARD_VISIBLE_HEIGHT).unwrap()
+    }
+
+    /// Number of columns of the board
+    pub fn width(&self) -> usize {
+        self.width as usize
+    }
+
+    /// Number of rows of the board, including the hidden rows
+    pub fn height(&self) -> usize {
+        self.height as usize
+    }
+
+    /// Number of visible rows of the board
+    pub fn visible_height(&self) -> usize {
+        self.visible_height as usize
+    }
+
+    /// Bitmask of a row with every tile filled
+    pub fn full_row(&self) -> u16 {
+        ((1u32 << self.width) - 1) as u16
     }
 
     /// Get the tile at position (x, y)
@@ -61,29 +195,33 @@ impl Board {
 
     /// Set an entire bitboard row
     pub fn set_row(&mut self, y: usize, row: u16) {
-        assert_eq!(row & !((1 << BOARD_WIDTH) - 1), 0);
+        assert!(y < self.height());
+        assert_eq!(row & !self.full_row(), 0);
         self.matrix[y] = row;
     }
 
-    /// Set the entire board from a given bitboard array
-    pub fn set_matrix(&mut self, matrix: [u16; BOARD_HEIGHT]) {
-        for row in matrix {
-            assert_eq!(row & !((1 << BOARD_WIDTH) - 1), 0);
+    /// Set the bottom rows of the board from a given bitboard array
+    pub fn set_matrix(&mut self, matrix: &[u16]) {
+        assert!(matrix.len() <= self.height());
+        for &row in matrix {
+            assert_eq!(row & !self.full_row(), 0);
         }
-        self.matrix = matrix;
+        self.matrix = [0; BOARD_MAX_HEIGHT];
+        self.matrix[..matrix.len()].copy_from_slice(matrix);
     }
 
     /// Add a number of garbage rows with a hole in the specified column
     pub fn add_garbage(&mut self, col: usize, height: u32) {
         let height = height as usize;
-        assert!(col < BOARD_WIDTH);
-        assert!(height < BOARD_HEIGHT);
+        let board_height = self.height();
+        assert!(col < self.width());
+        assert!(height < board_height);
         // Copy rows up
-        for j in (height..BOARD_HEIGHT).rev() {
+        for j in (height..board_height).rev() {
             self.matrix[j] = self.matrix[j - height];
         }
         // Set garbage rows
-        let garbage_row: u16 = ((1 << BOARD_WIDTH) - 1) & !(1 << col);
+        let garbage_row: u16 = self.full_row() & !(1 << col);
         for j in 0..height {
             self.matrix[j] = garbage_row;
         }
@@ -92,10 +230,13 @@ impl Board {
     /// Check whether a piece intersects with the board
     pub fn intersects_with(&self, piece: &Piece) -> bool {
         let p_y = piece.position_y as i32;
-        let shape = PieceInfo::bit_shape(piece.piece_type, piece.rotation, piece.position_x);
+        let shape =
+            piece
+                .rotation_system
+                .bit_shape(piece.piece_type, piece.rotation, piece.position_x);
         for j in 0..4 {
             let y = p_y + j as i32;
-            if y < 0 || y >= BOARD_HEIGHT as i32 {
+            if y < 0 || y >= self.height() as i32 {
                 continue;
             }
             let row = self.matrix[y as usize];
@@ -106,105 +247,154 @@ impl Board {
         false
     }
 
-    /// Check whether a piece is in a tspin position
-    pub fn check_tspin(&self, piece: &Piece) -> bool {
-        if piece.piece_type != PieceType::T {
-            return false;
+    /// Check whether a tile is filled, tiles outside of the walls and floor
+    /// are considered filled
+    fn filled_or_wall(&self, x: i8, y: i8) -> bool {
+        if x < 0 || x >= self.width as i8 || y < 0 {
+            return true;
         }
-        // Check if all 4 corners of T is in bounds
-        // We don't support tspin minis unfortunately
-        if !(0..=(BOARD_WIDTH as i8) - 3).contains(&piece.position_x)
-            || !(0..=(BOARD_HEIGHT as i8) - 3).contains(&piece.position_y)
-        {
+        if y >= self.height as i8 {
             return false;
         }
-        let bottom_idx = piece.position_y as usize;
-        let top_idx = bottom_idx + 2;
-        let bottom = (self.matrix[bottom_idx] >> piece.position_x) & 0b101;
-        let top = (self.matrix[top_idx] >> piece.position_x) & 0b101;
-        match piece.rotation {
-            0 => top == 0b101 && bottom != 0,
-            1 => (top == 0b101 && (bottom & 0b100 != 0)) || (bottom == 0b101 && (top & 0b100 != 0)),
-            2 => bottom == 0b101 && top != 0,
-            3 => (top == 0b101 && (bottom & 0b001 != 0)) || (bottom == 0b101 && (top & 0b001 != 0)),
-            _ => panic!("invalid rotation"),
+        self.get(x as usize, y as usize)
+    }
+
+    /// Check whether a piece is unable to move left, right or up
+    fn immobile(&self, piece: &Piece) -> bool {
+        [(-1, 0), (1, 0), (0, 1)].into_iter().all(|(d_x, d_y)| {
+            let mut piece = *piece;
+            !piece.shift(d_x, d_y, self)
+        })
+    }
+
+    /// Classify the spin of a piece using the 3-corner rule for T pieces and
+    /// immobility for other pieces (in all-spin mode). `kick` is the index of
+    /// the kick used by the last rotation, or None if the last move was not a
+    /// rotation.
+    pub fn check_spin(&self, piece: &Piece, kick: Option<u8>, spin_mode: SpinMode) -> SpinKind {
+        let Some(kick) = kick else {
+            return SpinKind::None;
+        };
+        match (piece.piece_type, spin_mode) {
+            (PieceType::T, _) => {}
+            (PieceType::O, _) | (_, SpinMode::TSpin) => return SpinKind::None,
+            (_, SpinMode::AllSpin) => {
+                return if self.immobile(piece) {
+                    SpinKind::Mini
+                } else {
+                    SpinKind::None
+                };
+            }
+        }
+
+        let corners = piece.rotation_system.t_corners(piece.rotation);
+        let filled = corners
+            .map(|(d_x, d_y)| self.filled_or_wall(piece.position_x + d_x, piece.position_y + d_y));
+        let front = filled[..2].iter().filter(|&&x| x).count();
+        let back = filled[2..].iter().filter(|&&x| x).count();
+        if front + back < 3 {
+            SpinKind::None
+        } else if front == 2
+            || piece.last_rotation.is_some_and(|from| {
+                piece
+                    .rotation_system
+                    .is_tst_kick(from, piece.rotation, kick)
+            })
+        {
+            SpinKind::Full
+        } else {
+            SpinKind::Mini
         }
     }
 
     /// Lock a piece onto the board
-    pub fn lock(&mut self, piece: &Piece) -> LockInfo {
-        // Check if it was a t-spin
-        let tspin = self.check_tspin(piece);
+    pub fn lock(&mut self, piece: &Piece, spin_mode: SpinMode) -> LockInfo {
+        // Check if it was a spin
+        let spin = self.check_spin(piece, piece.last_kick, spin_mode);
 
         // Write piece onto board
         let p_y = piece.position_y as i32;
-        let shape = PieceInfo::bit_shape(piece.piece_type, piece.rotation, piece.position_x);
+        let shape =
+            piece
+                .rotation_system
+                .bit_shape(piece.piece_type, piece.rotation, piece.position_x);
         for j in 0..4 {
             let y = p_y + j;
-            if !(0..(BOARD_HEIGHT as i32)).contains(&y) {
+            if !(0..(self.height() as i32)).contains(&y) {
                 continue;
             }
             self.matrix[y as usize] |= shape[j as usize];
         }
 
         // Check for cleared lines
+        let height = self.height();
+        let full_row = self.full_row();
         let mut lines_cleared = 0;
-        for j in 0..BOARD_HEIGHT {
-            if self.matrix[j] == (1 << BOARD_WIDTH) - 1 {
+        for j in 0..height {
+            if self.matrix[j] == full_row {
                 lines_cleared += 1;
             } else {
                 self.matrix[j - lines_cleared] = self.matrix[j];
             }
         }
         for j in 0..lines_cleared {
-            self.matrix[BOARD_HEIGHT - lines_cleared + j] = 0;
+            self.matrix[height - lines_cleared + j] = 0;
         }
 
         // Check for top-out
         let top_out = self.topped_out();
 
+        let perfect_clear = lines_cleared > 0 && self.matrix.iter().all(|&row| row == 0);
+
         LockInfo {
             lines_cleared: lines_cleared as u8,
             top_out,
-            tspin,
+            spin,
+            perfect_clear,
+            combo: 0,
+            back_to_back: false,
+            attack: 0,
         }
     }
 
     /// Check whether the board is currently topped out
     pub fn topped_out(&self) -> bool {
-        self.matrix[BOARD_VISIBLE_HEIGHT] != 0
+        self.matrix[self.visible_height()] != 0
     }
 
     /// Returns the maximum height of a column of the board
     pub fn max_height(&self) -> i8 {
-        for i in 0..BOARD_HEIGHT {
+        for i in 0..self.height() {
             if self.matrix[i] == 0 {
                 return i as i8;
             }
         }
-        BOARD_HEIGHT as i8
+        self.height as i8
     }
 
     /// Gives a count of the number of holes in each column. A hole is defined
     /// as an empty tile that has a full tile somewhere above it.
-    pub fn holes(&self) -> [i8; BOARD_WIDTH] {
-        let mut holes = [0; BOARD_WIDTH];
+    pub fn holes(&self) -> ColumnMap {
+        let mut holes = [0; BOARD_MAX_WIDTH];
         let height_map = self.height_map();
-        for i in 0..BOARD_WIDTH {
+        for i in 0..self.width() {
             for j in 0..(height_map[i] - 1) {
                 if !self.get(i, j as usize) {
                     holes[i] += 1;
                 }
             }
         }
-        holes
+        ColumnMap {
+            values: holes,
+            width: self.width,
+        }
     }
 
     /// Gives the row of the highest tile in each column.
-    pub fn height_map(&self) -> [i8; BOARD_WIDTH] {
-        let mut height_map = [0; BOARD_WIDTH];
+    pub fn height_map(&self) -> ColumnMap {
+        let mut height_map = [0; BOARD_MAX_WIDTH];
         let max_height = self.max_height();
-        for i in 0..BOARD_WIDTH {
+        for i in 0..self.width() {
             for j in (0..max_height).rev() {
                 if self.get(i, j as usize) {
                     height_map[i] = j + 1;
@@ -212,18 +402,23 @@ impl Board {
                 }
             }
         }
-        height_map
+        ColumnMap {
+            values: height_map,
+            width: self.width,
+        }
     }
 
     /// Return a string representation of the board
     pub fn to_string(&self, piece: Option<&Piece>) -> String {
         let mut text = String::new();
-        for j in (0..BOARD_HEIGHT).rev() {
+        for j in (0..self.height()).rev() {
             write!(text, "{j:>2}").unwrap();
-            for i in 0..BOARD_WIDTH {
+            for i in 0..self.width() {
                 let (in_piece_bounds, in_piece) = match piece {
                     Some(piece) => {
-                        let piece_shape = PieceInfo::shape(piece.piece_type, piece.rotation);
+                        let piece_shape = piece
+                            .rotation_system
+                            .shape(piece.piece_type, piece.rotation);
                         let p_x = piece.position_x as usize;
                         let p_y = piece.position_y as usize;
                         let x = i as i8 - p_x as i8;
@@ -248,7 +443,7 @@ impl Board {
             writeln!(text).unwrap();
         }
         write!(text, "  ").unwrap();
-        for i in 0..BOARD_WIDTH {
+        for i in 0..self.width() {
             write!(text, "{i:>2}").unwrap();
         }
         writeln!(text).unwrap();
@@ -261,3 +456,121 @@ impl Display for Board {
         write!(f, "{}", self.to_string(None))
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{ActionInfo, Fin, Game, RotationSystem};
+
+    const FULL: u16 = (1 << BOARD_WIDTH) - 1;
+
+    #[test]
+    fn test_check_spin() {
+        // T-spin double slot with an overhang on the left
+        let mut board = Board::new();
+        board.set_row(0, FULL & !(1 << 2));
+        board.set_row(1, FULL & !(0b111 << 1));
+        board.set_row(2, 1 << 1);
+        let piece = Piece::from_parts(PieceType::T, 2, 1, 0);
+        assert_eq!(
+            board.check_spin(&piece, Some(0), SpinMode::TSpin),
+            SpinKind::Full
+        );
+        assert_eq!(
+            board.check_spin(&piece, None, SpinMode::TSpin),
+            SpinKind::None
+        );
+        let piece = Piece {
+            last_kick: Some(0),
+            ..piece
+        };
+        let info = board.lock(&piece, SpinMode::TSpin);
+        assert_eq!(info.lines_cleared, 2);
+        assert_eq!(info.spin, SpinKind::Full);
+
+        // T pointing right against the left wall is a mini, unless the
+        // last kick was used
+        let mut board = Board::new();
+        board.set_row(0, FULL & !1);
+        board.set_row(1, FULL & !0b11);
+        let piece = Piece {
+            last_rotation: Some(0),
+            ..Piece::from_parts(PieceType::T, 1, -1, 0)
+        };
+        assert_eq!(
+            board.check_spin(&piece, Some(0), SpinMode::TSpin),
+            SpinKind::Mini
+        );
+        assert_eq!(
+            board.check_spin(&piece, Some(4), SpinMode::TSpin),
+            SpinKind::Full
+        );
+
+        // Only quarter turn SRS kicks are upgraded
+        let half_turn = Piece {
+            last_rotation: Some(3),
+            rotation_system: RotationSystem::SrsPlus,
+            ..piece
+        };
+        assert_eq!(
+            board.check_spin(&half_turn, Some(4), SpinMode::TSpin),
+            SpinKind::Mini
+        );
+        assert!(RotationSystem::SrsPlus.is_tst_kick(2, 1, 4));
+        assert!(!RotationSystem::Ars.is_tst_kick(0, 1, 4));
+
+        // Immobile non-T pieces only spin in all-spin mode
+        let mut board = Board::new();
+        board.set_row(0, FULL & !(0b11 << 2));
+        board.set_row(1, FULL & !(0b11 << 1));
+        let piece = Piece::from_parts(PieceType::Z, 0, 1, -1);
+        assert!(!board.intersects_with(&piece));
+        assert_eq!(
+            board.check_spin(&piece, Some(0), SpinMode::TSpin),
+            SpinKind::None
+        );
+        assert_eq!(
+            board.check_spin(&piece, Some(0), SpinMode::AllSpin),
+            SpinKind::Mini
+        );
+    }
+
+    #[test]
+    fn test_board_size() {
+        assert!(Board::with_size(17, 24, 20).is_err());
+        assert!(Board::with_size(10, 41, 20).is_err());
+        assert!(Board::with_size(10, 24, 21).is_err());
+
+        // Pieces spawn centered above the visible area
+        let board = Board::with_size(4, 12, 8).unwrap();
+        let mut game = Game::from_pieces(PieceType::I, None, &[PieceType::O; 7]);
+        game.set_board(board);
+        assert_eq!((game.active.position_x, game.active.position_y), (0, 7));
+        assert!(!game.active.shift_left(&game.board));
+        assert!(!game.active.shift_right(&game.board));
+
+        // Lines clear when all 4 columns are filled
+        let ActionInfo::Lock(info) = game.hard_drop() else {
+            panic!("expected lock");
+        };
+        assert_eq!(info.lines_cleared, 1);
+        assert!(info.perfect_clear);
+
+        // Every child stays inside a 6 wide board
+        let mut game = Game::from_pieces(PieceType::T, None, &[PieceType::I; 7]);
+        game.set_board(Board::with_size(6, 24, 20).unwrap());
+        let children = game.children(Fin::Simple1);
+        // T: 4 + 5 + 4 + 5 placements, held I: 3 + 6 placements
+        assert_eq!(children.len(), 27);
+        for child in children {
+            assert!(child.game.board.matrix.iter().all(|&row| row < 1 << 6));
+            assert_eq!(child.game.board.height_map().len(), 6);
+        }
+
+        // Guideline boards have 20 hidden rows
+        let board = Board::guideline();
+        assert_eq!((board.width(), board.height()), (10, 40));
+        let piece = Piece::spawn(PieceType::T, RotationSystem::Srs, &board);
+        assert_eq!((piece.position_x, piece.position_y), (3, 20));
+    }
+}
@@ -1,4 +1,4 @@
-use crate::{Action, Game, PieceInfo};
+use crate::{Action, Game};
 use std::sync::LazyLock;
 
 use super::{ActionInfo, LockInfo};
@@ -211,8 +211,11 @@ impl Game {
                     continue;
                 }
 
-                let (min_x, max_x, _, _) =
-                    PieceInfo::location_bound(game.active.piece_type, game.active.rotation);
+                let (min_x, max_x, _, _) = game.active.rotation_system.location_bound(
+                    game.active.piece_type,
+                    game.active.rotation,
+                    &game.board,
+                );
 
                 // Piece shift
                 'shift: for position_x in min_x..=max_x {
@@ -379,6 +382,7 @@ impl BoardSet {
         if child.hold {
             hash ^= NOISE;
         }
+        hash ^= child.lock_info.spin as u64;
         hash
     }
 
@@ -386,7 +390,10 @@ impl BoardSet {
         let mut idx = self.hash(child) as usize % BUCKETS;
 
         while let Some(entry) = &mut self.entries[idx] {
-            if entry.hold == child.hold && entry.game.board == child.game.board {
+            if entry.hold == child.hold
+                && entry.game.board == child.game.board
+                && entry.lock_info.spin == child.lock_info.spin
+            {
                 if child.fin_idx < entry.fin_idx {
                     *entry = child;
                 }
@@ -0,0 +1,273 @@
+use super::{BagKind, PieceType};
+use std::collections::HashMap;
+
+/// The probability of each piece type appearing in a slot after the queue
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct PieceDistribution {
+    probabilities: [f32; 7],
+}
+
+impl PieceDistribution {
+    /// Every piece type is equally likely
+    pub fn uniform() -> Self {
+        PieceDistribution {
+            probabilities: [1. / 7.; 7],
+        }
+    }
+
+    pub fn certain(piece_type: PieceType) -> Self {
+        let mut probabilities = [0.; 7];
+        probabilities[piece_type.to_u8() as usize] = 1.;
+        PieceDistribution { probabilities }
+    }
+
+    /// Normalize weights indexed by `PieceType::to_u8`, falls back to a
+    /// uniform distribution if every weight is 0
+    pub(super) fn from_weights(weights: [f64; 7]) -> Self {
+        let total = weights.iter().sum::<f64>();
+        if total <= 0. {
+            return PieceDistribution::uniform();
+        }
+        PieceDistribution {
+            probabilities: weights.map(|weight| (weight / total) as f32),
+        }
+    }
+
+    pub fn probability(&self, piece_type: PieceType) -> f32 {
+        self.probabilities[piece_type.to_u8() as usize]
+    }
+
+    /// Piece types that can appear in this slot, the most likely first
+    pub fn possible(&self) -> Vec<PieceType> {
+        let mut possible = PieceType::ALL
+            .into_iter()
+            .filter(|&piece_type| self.probability(piece_type) > 0.)
+            .collect::<Vec<_>>();
+        possible.sort_by(|&a, &b| self.probability(b).total_cmp(&self.probability(a)));
+        possible
+    }
+
+    pub fn most_likely(&self) -> PieceType {
+        self.possible()[0]
+    }
+}
+
+impl BagKind {
+    /// The distribution of each of the next `count` pieces, given the pieces
+    /// most recently dealt from a bag of this kind, such as the queue of a
+    /// game. How far into the bag `recent` ends is inferred from the pieces
+    /// themselves, the seed of the bag is not used.
+    pub fn next_pieces(&self, recent: &[PieceType], count: usize) -> Vec<PieceDistribution> {
+        let slots = match self {
+            BagKind::Fixed { sequence } => fixed_slots(sequence, recent, count),
+            BagKind::Rng7 { .. } => shuffled_slots(1, recent, count),
+            BagKind::Rng14 { .. } => shuffled_slots(2, recent, count),
+            BagKind::Random { .. } => vec![[1.; 7]; count],
+            BagKind::Weighted { weights, .. } => vec![weights.map(|x| x as f64); count],
+            BagKind::Tgm { .. } => {
+                let mut history = [None; 4];
+                for (slot, &piece_type) in history.iter_mut().rev().zip(recent.iter().rev()) {
+                    *slot = Some(piece_type);
+                }
+                tgm_slots(history, false, count)
+            }
+        };
+        slots
+            .into_iter()
+            .map(PieceDistribution::from_weights)
+            .collect()
+    }
+}
+
+pub(super) fn one_hot(piece_type: PieceType) -> [f64; 7] {
+    let mut weights = [0.; 7];
+    weights[piece_type.to_u8() as usize] = 1.;
+    weights
+}
+
+/// Every alignment of the sequence that matches the recent pieces is equally
+/// likely
+fn fixed_slots(sequence: &[PieceType], recent: &[PieceType], count: usize) -> Vec<[f64; 7]> {
+    let len = sequence.len();
+    let mut slots = vec![[0.; 7]; count];
+    for start in 0..len {
+        let matches = recent
+            .iter()
+            .enumerate()
+            .all(|(i, &piece_type)| sequence[(start + i) % len] == piece_type);
+        if !matches {
+            continue;
+        }
+        for (i, slot) in slots.iter_mut().enumerate() {
+            slot[sequence[(start + recent.len() + i) % len].to_u8() as usize] += 1.;
+        }
+    }
+    slots
+}
+
+/// Each remaining slot of the current bag is equally likely to hold any of
+/// the remaining pieces, later bags are uniform
+pub(super) fn bag_slots(remaining: [u32; 7], left: usize, count: usize) -> Vec<[f64; 7]> {
+    (0..count)
+        .map(|i| match i < left {
+            true => remaining.map(|x| x as f64),
+            false => [1.; 7],
+        })
+        .collect()
+}
+
+/// Probability of dealing a sequence of pieces in a row from a bag holding
+/// `copies` of each piece
+fn bag_sequence_probability(pieces: &[PieceType], copies: u32) -> f64 {
+    let mut counts = [copies; 7];
+    let mut total = 7 * copies;
+    let mut probability = 1.;
+    for &piece_type in pieces {
+        let count = &mut counts[piece_type.to_u8() as usize];
+        if *count == 0 {
+            return 0.;
+        }
+        probability *= *count as f64 / total as f64;
+        *count -= 1;
+        total -= 1;
+    }
+    probability
+}
+
+/// Mix the distributions for every possible position in the current bag,
+/// weighted by how likely the recent pieces are given that position
+fn shuffled_slots(copies: u32, recent: &[PieceType], count: usize) -> Vec<[f64; 7]> {
+    let size = 7 * copies as usize;
+    let mut slots = vec![[0.; 7]; count];
+    for dealt in 0..size {
+        // The last `dealt` pieces are from the current bag
+        let split = recent.len() - dealt.min(recent.len());
+        let (previous, current) = recent.split_at(split);
+        let mut weight = bag_sequence_probability(current, copies);
+        for bag in previous.rchunks(size) {
+            weight *= bag_sequence_probability(bag, copies);
+        }
+        if weight == 0. {
+            continue;
+        }
+        let mut remaining = [copies; 7];
+        for &piece_type in current {
+            remaining[piece_type.to_u8() as usize] -= 1;
+        }
+        for (slot, weights) in slots
+            .iter_mut()
+            .zip(bag_slots(remaining, size - dealt, count))
+        {
+            let total = weights.iter().sum::<f64>();
+            for (x, w) in slot.iter_mut().zip(weights) {
+                *x += w * weight / total;
+            }
+        }
+    }
+    slots
+}
+
+/// Distribution of the next piece of the TGM randomizer given its history,
+/// assuming an even pool. Each roll picks a piece in the history with
+/// probability `h / 7`, the 6th roll is always kept.
+fn tgm_next(history: &[Option<PieceType>; 4]) -> [f64; 7] {
+    let mut in_history = [false; 7];
+    for piece_type in history.iter().flatten() {
+        in_history[piece_type.to_u8() as usize] = true;
+    }
+    let count = in_history.iter().filter(|&&x| x).count() as f64;
+    let reroll = (count / 7.).powi(6);
+    in_history.map(|x| match x {
+        true => reroll / count,
+        false => (1. - reroll) / (7. - count),
+    })
+}
+
+/// Track the probability of every possible history to find the distribution
+/// of later slots
+pub(super) fn tgm_slots(
+    history: [Option<PieceType>; 4],
+    first: bool,
+    count: usize,
+) -> Vec<[f64; 7]> {
+    let mut states = HashMap::from([(history, 1.)]);
+    let mut slots = Vec::new();
+    for i in 0..count {
+        let mut slot = [0.; 7];
+        let mut next_states = HashMap::new();
+        for (history, probability) in states {
+            let next = match first && i == 0 {
+                true => {
+                    let mut next = [0.; 7];
+                    for piece_type in [PieceType::I, PieceType::T, PieceType::L, PieceType::J] {
+                        next[piece_type.to_u8() as usize] = 0.25;
+                    }
+                    next
+                }
+                false => tgm_next(&history),
+            };
+            for (piece_type, chance) in PieceType::ALL.into_iter().zip(next) {
+                if chance == 0. {
+                    continue;
+                }
+                slot[piece_type.to_u8() as usize] += probability * chance;
+                let mut history = history;
+                history.rotate_left(1);
+                history[3] = Some(piece_type);
+                *next_states.entry(history).or_insert(0.) += probability * chance;
+            }
+        }
+        slots.push(slot);
+        states = next_states;
+    }
+    slots
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::model::Bag;
+    use PieceType::*;
+
+    #[test]
+    fn test_next_pieces() {
+        // Any alignment of the 7-bag fits distinct pieces, but the rest of
+        // the current bag is more likely
+        let kind = BagKind::Rng7 { seed: 0 };
+        let next = kind.next_pieces(&[S, Z, O, I, T], 3);
+        assert_eq!(next[0].possible().len(), 7);
+        assert!(next[0].probability(L) > 1. / 7.);
+        assert!(next[0].probability(S) < 1. / 7.);
+        assert_eq!(next[0].most_likely(), next[1].most_likely());
+
+        // The bag boundary can be inferred from a repeated piece
+        let next = kind.next_pieces(&[T, S, T], 1);
+        assert_eq!(next[0].probability(T), 0.);
+
+        // Matches the state of the bag itself
+        let mut bag = Bag::new_rng7(1);
+        for _ in 0..10 {
+            bag.next();
+        }
+        let next = bag.next_pieces(5);
+        assert_eq!(next[0].possible().len(), 4);
+        assert_eq!(next[4], PieceDistribution::uniform());
+
+        let kind = BagKind::Fixed {
+            sequence: vec![I, O, T],
+        };
+        let next = kind.next_pieces(&[O], 2);
+        assert_eq!(
+            next,
+            [PieceDistribution::certain(T), PieceDistribution::certain(I)]
+        );
+
+        // Recent pieces are unlikely with the TGM randomizer
+        let kind = BagKind::Tgm { seed: 0 };
+        let next = kind.next_pieces(&[S, Z, O, I], 2);
+        assert!(next[0].probability(S) < 0.01);
+        assert!(next[1].probability(Z) < next[1].probability(T));
+        let next = Bag::new_tgm(0).next_pieces(1);
+        assert_eq!(next[0].probability(O), 0.);
+    }
+}
@@ -0,0 +1,311 @@
+use super::movegen::{state_index, CollisionMap, STATES};
+use crate::{Action, Game, Piece, SpinKind};
+use anyhow::{bail, Result};
+use std::{cmp::Reverse, collections::BinaryHeap};
+
+/// How soft drops are counted by the finesse pathfinder
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SoftDropCost {
+    /// The piece falls to the bottom at once for a fixed cost, it can't stop
+    /// part of the way down
+    Instant(u32),
+    /// The piece falls one row at a time, each row has a cost
+    Stepped(u32),
+}
+
+/// Cost of each kind of input used by the finesse pathfinder
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FinesseCost {
+    pub shift: u32,
+    /// Cost of shifting all the way to a wall with DAS, `None` if DAS is not
+    /// used
+    pub das: Option<u32>,
+    pub rotate: u32,
+    /// `None` if 180 rotations are not allowed
+    pub rotate_180: Option<u32>,
+    pub soft_drop: SoftDropCost,
+    pub hold: u32,
+    pub hard_drop: u32,
+}
+
+impl Default for FinesseCost {
+    fn default() -> Self {
+        FinesseCost {
+            shift: 1,
+            das: Some(1),
+            rotate: 1,
+            rotate_180: Some(1),
+            soft_drop: SoftDropCost::Instant(1),
+            hold: 1,
+            hard_drop: 1,
+        }
+    }
+}
+
+/// The cheapest list of inputs that locks a piece at a target placement
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FinessePath {
+    /// Ends in a hard drop
+    pub actions: Vec<Action>,
+    pub cost: u32,
+}
+
+/// A search node, `repeat` is the number of times the action is applied
+#[derive(Clone, Copy)]
+struct Node {
+    piece: Piece,
+    parent: u32,
+    action: Action,
+    repeat: u8,
+    goal: bool,
+}
+
+/// The tiles covered by a piece, as the row of its lowest tile and the bit
+/// rows upwards from there. Rotations of S, Z, I and O pieces can cover the
+/// same tiles at different positions.
+fn tiles(piece: &Piece) -> (i8, [u16; 4]) {
+    let mut shape =
+        piece
+            .rotation_system
+            .bit_shape(piece.piece_type, piece.rotation, piece.position_x);
+    let bottom = shape.iter().take_while(|&&row| row == 0).count();
+    shape.rotate_left(bottom);
+    (piece.position_y + bottom as i8, shape)
+}
+
+impl Game {
+    /// Find the cheapest list of inputs that locks the active piece (or the
+    /// hold piece) on the same tiles as `target`, in any rotation. If
+    /// `target.last_kick` is set, the piece must also lock with the same kind
+    /// of spin as the target.
+    pub fn finesse(&self, target: &Piece, cost: &FinesseCost) -> Result<FinessePath> {
+        let target = Piece {
+            rotation_system: self.active.rotation_system,
+            ..*target
+        };
+
+        let mut starts = Vec::new();
+        if self.active.piece_type == target.piece_type {
+            starts.push((*self, false));
+        }
+        let mut held = *self;
+        if held.swap_hold() && held.active.piece_type == target.piece_type {
+            starts.push((held, true));
+        }
+        if starts.is_empty() {
+            bail!(
+                "target piece {} is neither the active nor the hold piece",
+                target.piece_type
+            );
+        }
+
+        let map = CollisionMap::new(&self.board, &target);
+        if map.collides(target.rotation, target.position_x, target.position_y) {
+            bail!("target {target} is out of bounds or intersects the board");
+        }
+        if !map.grounded(&target) {
+            bail!("target {target} is not resting on the stack");
+        }
+        let spin = target
+            .last_kick
+            .map(|kick| self.board.check_spin(&target, Some(kick), self.spin_mode));
+
+        let mut best: Option<FinessePath> = None;
+        for (game, hold) in starts {
+            let Some(mut path) = game.search_finesse(&map, &target, spin, cost) else {
+                continue;
+            };
+            if hold {
+                path.actions.insert(0, Action::Hold);
+                path.cost += cost.hold;
+            }
+            if best.as_ref().is_none_or(|best| path.cost < best.cost) {
+                best = Some(path);
+            }
+        }
+        match best {
+            Some(path) => Ok(path),
+            None => bail!("target {target} can't be reached from the spawn position"),
+        }
+    }
+
+    /// Dijkstra's algorithm over every position, rotation and kick
+    fn search_finesse(
+        &self,
+        map: &CollisionMap,
+        target: &Piece,
+        spin: Option<SpinKind>,
+        cost: &FinesseCost,
+    ) -> Option<FinessePath> {
+        let board = &self.board;
+        let target_tiles = tiles(target);
+        let mut dist = vec![u32::MAX; STATES];
+        let mut nodes = vec![Node {
+            piece: self.active,
+            parent: 0,
+            action: Action::HardDrop,
+            repeat: 0,
+            goal: false,
+        }];
+        let mut queue = BinaryHeap::new();
+        dist[state_index(&self.active)] = 0;
+        queue.push(Reverse((0, 0)));
+
+        while let Some(Reverse((d, idx))) = queue.pop() {
+            let node = nodes[idx as usize];
+            if node.goal {
+                let mut actions = vec![Action::HardDrop];
+                let mut i = node.parent as usize;
+                while i != 0 {
+                    for _ in 0..nodes[i].repeat {
+                        actions.push(nodes[i].action);
+                    }
+                    i = nodes[i].parent as usize;
+                }
+                actions.reverse();
+                return Some(FinessePath { actions, cost: d });
+            }
+            let piece = node.piece;
+            if d > dist[state_index(&piece)] {
+                continue;
+            }
+
+            // Hard drop if it locks the piece at the target
+            let landing = map.drop(&piece).unwrap_or(piece);
+            let matches = tiles(&landing) == target_tiles
+                && spin.is_none_or(|spin| {
+                    board.check_spin(&landing, landing.last_kick, self.spin_mode) == spin
+                });
+            if matches {
+                nodes.push(Node {
+                    piece,
+                    parent: idx,
+                    action: Action::HardDrop,
+                    repeat: 1,
+                    goal: true,
+                });
+                queue.push(Reverse((d + cost.hard_drop, nodes.len() as u32 - 1)));
+            }
+
+            let mut moves = vec![
+                (Action::ShiftLeft, 1, map.shift(&piece, -1, 0), cost.shift),
+                (Action::ShiftRight, 1, map.shift(&piece, 1, 0), cost.shift),
+                (
+                    Action::RotateCw,
+                    1,
+                    map.rotate(&piece, 1, board),
+                    cost.rotate,
+                ),
+                (
+                    Action::RotateCcw,
+                    1,
+                    map.rotate(&piece, 3, board),
+                    cost.rotate,
+                ),
+            ];
+            if let Some(rotate_180) = cost.rotate_180 {
+                moves.push((
+                    Action::Rotate180,
+                    1,
+                    map.rotate(&piece, 2, board),
+                    rotate_180,
+                ));
+            }
+            match cost.soft_drop {
+                SoftDropCost::Instant(c) => moves.push((Action::SoftDrop, 1, map.drop(&piece), c)),
+                SoftDropCost::Stepped(c) => {
+                    moves.push((Action::ShiftDown, 1, map.shift(&piece, 0, -1), c))
+                }
+            }
+            if let Some(das) = cost.das {
+                for (action, d_x) in [(Action::ShiftLeft, -1), (Action::ShiftRight, 1)] {
+                    let mut wall = piece;
+                    let mut repeat = 0;
+                    while let Some(next) = map.shift(&wall, d_x, 0) {
+                        wall = next;
+                        repeat += 1;
+                    }
+                    if repeat > 1 {
+                        moves.push((action, repeat, Some(wall), das));
+                    }
+                }
+            }
+
+            for (action, repeat, next, c) in moves {
+                let Some(next) = next else {
+                    continue;
+                };
+                let state = state_index(&next);
+                if d + c >= dist[state] {
+                    continue;
+                }
+                dist[state] = d + c;
+                nodes.push(Node {
+                    piece: next,
+                    parent: idx,
+                    action,
+                    repeat,
+                    goal: false,
+                });
+                queue.push(Reverse((d + c, nodes.len() as u32 - 1)));
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Board, PieceType};
+
+    #[test]
+    fn test_finesse() {
+        let game = Game::from_pieces(PieceType::T, Some(PieceType::I), &[PieceType::O]);
+        let mut target = game.active;
+        target.position_x = 0;
+        target.soft_drop(&game.board);
+
+        let path = game.finesse(&target, &FinesseCost::default()).unwrap();
+        assert_eq!(path.cost, 2);
+        assert_eq!(path.actions.last(), Some(&Action::HardDrop));
+        let mut new_game = game;
+        for &action in path.actions[..path.actions.len() - 1].iter() {
+            new_game.apply(action);
+        }
+        new_game.active.soft_drop(&new_game.board);
+        assert_eq!(new_game.active, target);
+
+        let cost = FinesseCost {
+            das: None,
+            ..Default::default()
+        };
+        assert_eq!(game.finesse(&target, &cost).unwrap().cost, 4);
+
+        // The hold piece is used when it matches the target
+        let mut target = Piece::from_piece_type(PieceType::I);
+        target.soft_drop(&Board::new());
+        let path = game.finesse(&target, &FinesseCost::default()).unwrap();
+        assert_eq!(path.actions, vec![Action::Hold, Action::HardDrop]);
+
+        // An S piece in rotation 2 covers the same tiles as one dropped from
+        // spawn, so no rotation is needed
+        let game = Game::from_pieces(PieceType::S, None, &[PieceType::O]);
+        let mut target = game.active;
+        assert!(target.rotate_180(&game.board));
+        target.soft_drop(&game.board);
+        let path = game.finesse(&target, &FinesseCost::default()).unwrap();
+        assert_eq!(path.actions, vec![Action::HardDrop]);
+        let mut dropped = game;
+        dropped.apply(Action::HardDrop);
+        let mut locked = game.board;
+        locked.lock(&target, game.spin_mode);
+        assert_eq!(dropped.board, locked);
+
+        // Unreachable targets
+        let target = Piece::from_piece_type(PieceType::O);
+        assert!(game.finesse(&target, &FinesseCost::default()).is_err());
+        let target = game.active;
+        assert!(game.finesse(&target, &FinesseCost::default()).is_err());
+    }
+}
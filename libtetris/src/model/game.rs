@@ -2,8 +2,12 @@ use crate::model::board::Board;
 use crate::model::piece::Piece;
 use crate::model::piece::PieceType;
 use crate::model::Bag;
+use crate::AttackTable;
 use crate::LockInfo;
 use crate::PieceQueue;
+use crate::RotationSystem;
+use crate::SpinKind;
+use crate::SpinMode;
 use crate::PIECE_QUEUE_MAX_LEN;
 use anyhow::anyhow;
 use anyhow::Result;
@@ -78,7 +82,8 @@ impl Display for Action {
 }
 
 /// Information after performing an action
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
 pub enum ActionInfo {
     Success,
     Lock(LockInfo),
@@ -112,6 +117,34 @@ pub struct Game {
     pub hold: Option<PieceType>,
     pub queue: PieceQueue,
     pub can_hold: bool,
+    /// Number of consecutive locks that cleared lines
+    #[serde(default)]
+    pub combo: u8,
+    /// Number of consecutive difficult clears (tetrises and t-spins)
+    #[serde(default)]
+    pub back_to_back: u8,
+    /// Attack table used to compute the attack sent on lock
+    #[serde(default)]
+    pub attack_table: AttackTable,
+    /// Which pieces are able to perform spins
+    #[serde(default)]
+    pub spin_mode: SpinMode,
+    /// Number of upcoming pieces visible to the player, `refill_queue`
+    /// only fills the queue up to this length
+    #[serde(default = "default_preview_len")]
+    pub preview_len: u8,
+    /// Whether the hold action is disabled for the whole game
+    #[serde(default)]
+    pub hold_disabled: bool,
+    /// The active piece hasn't been dealt yet, because the queue was empty
+    /// when the last piece was locked or held, such as when no pieces are
+    /// previewed. Every action fails until `refill_queue` deals it.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub active_unknown: bool,
+}
+
+fn default_preview_len() -> u8 {
+    PIECE_QUEUE_MAX_LEN as u8
 }
 
 impl Game {
@@ -135,23 +168,45 @@ impl Game {
             hold,
             queue: piece_queue,
             can_hold,
+            combo: 0,
+            back_to_back: 0,
+            attack_table: AttackTable::default(),
+            spin_mode: SpinMode::default(),
+            preview_len: default_preview_len(),
+            hold_disabled: false,
+            active_unknown: false,
         }
     }
 
     /// Create a game from a given bag
     pub fn from_bag(bag: &mut Bag) -> Self {
+        Game::from_bag_with_preview(bag, PIECE_QUEUE_MAX_LEN)
+    }
+
+    /// Create a game from a given bag that shows `preview_len` upcoming
+    /// pieces. With no preview, each piece is only dealt once it is needed.
+    pub fn from_bag_with_preview(bag: &mut Bag, preview_len: usize) -> Self {
+        assert!(
+            preview_len <= PIECE_QUEUE_MAX_LEN,
+            "preview length must be at most {PIECE_QUEUE_MAX_LEN}"
+        );
         let active = Piece::from_piece_type(bag.next());
-        let mut queue = PieceQueue::new();
-        while queue.len() < PIECE_QUEUE_MAX_LEN {
-            queue.enqueue(bag.next());
-        }
-        Game {
+        let mut game = Game {
             board: Board::new(),
             active,
             hold: None,
-            queue,
+            queue: PieceQueue::new(),
             can_hold: true,
-        }
+            combo: 0,
+            back_to_back: 0,
+            attack_table: AttackTable::default(),
+            spin_mode: SpinMode::default(),
+            preview_len: preview_len as u8,
+            hold_disabled: false,
+            active_unknown: false,
+        };
+        game.refill_queue(bag);
+        game
     }
 
     /// Create a game from a list of pieces
@@ -168,49 +223,104 @@ impl Game {
             hold,
             queue: piece_queue,
             can_hold: true,
+            combo: 0,
+            back_to_back: 0,
+            attack_table: AttackTable::default(),
+            spin_mode: SpinMode::default(),
+            preview_len: default_preview_len(),
+            hold_disabled: false,
+            active_unknown: false,
         }
     }
 
-    /// Refill the game's queue with the given bag
+    /// Change the rotation system of the game, the active piece is moved back
+    /// to its spawn location
+    pub fn set_rotation_system(&mut self, rotation_system: RotationSystem) {
+        self.active.rotation_system = rotation_system;
+        self.active.reset(&self.board);
+    }
+
+    /// Replace the board of the game, the active piece is moved back to its
+    /// spawn location on the new board
+    pub fn set_board(&mut self, board: Board) {
+        self.board = board;
+        self.active.reset(&self.board);
+    }
+
+    /// Refill the game's queue with the given bag, up to the preview length.
+    /// An active piece that wasn't dealt yet is dealt first.
     pub fn refill_queue(&mut self, bag: &mut Bag) {
-        while self.queue.len() < PIECE_QUEUE_MAX_LEN {
+        if self.active_unknown {
+            self.deal_active(bag.next());
+        }
+        while self.queue.len() < self.preview_len as usize {
             self.queue.enqueue(bag.next());
         }
     }
 
+    /// Deal the active piece after a lock or hold left it unknown
+    pub(crate) fn deal_active(&mut self, piece_type: PieceType) {
+        self.active.piece_type = piece_type;
+        self.active.reset(&self.board);
+        self.active_unknown = false;
+    }
+
+    /// Make the next piece in the queue active, it stays unknown if the
+    /// queue is empty
+    fn next_piece(&mut self) {
+        match self.queue.dequeue() {
+            Some(piece_type) => self.active.piece_type = piece_type,
+            None => self.active_unknown = true,
+        }
+        self.active.reset(&self.board);
+    }
+
     pub fn swap_hold(&mut self) -> bool {
-        if !self.can_hold {
+        if !self.can_hold || self.hold_disabled || self.active_unknown {
             return false;
         }
-        let hold = match self.hold {
-            Some(hold) => hold,
-            None => match self.queue.dequeue() {
-                Some(piece) => piece,
-                None => return false,
-            },
-        };
-        self.hold = Some(self.active.piece_type);
-        self.active.piece_type = hold;
-        self.active.reset();
+        let active = self.active.piece_type;
+        match self.hold {
+            Some(hold) => {
+                self.active.piece_type = hold;
+                self.active.reset(&self.board);
+            }
+            None => self.next_piece(),
+        }
+        self.hold = Some(active);
         self.can_hold = false;
         true
     }
 
     pub fn lock(&mut self) -> ActionInfo {
-        if self.queue.is_empty() {
+        if self.active_unknown {
             return ActionInfo::Fail;
         }
 
-        let info = self.board.lock(&self.active);
-        self.active.piece_type = self.queue.dequeue().unwrap();
-        self.active.reset();
+        let mut info = self.board.lock(&self.active, self.spin_mode);
+        if info.lines_cleared > 0 {
+            let difficult = info.spin != SpinKind::None || info.lines_cleared == 4;
+            info.back_to_back = difficult && self.back_to_back > 0;
+            self.back_to_back = if difficult {
+                self.back_to_back.saturating_add(1)
+            } else {
+                0
+            };
+            self.combo = self.combo.saturating_add(1);
+        } else {
+            self.combo = 0;
+        }
+        info.combo = self.combo;
+        info.attack = self.attack_table.attack(&info);
+
+        self.next_piece();
         self.can_hold = true;
 
         ActionInfo::Lock(info)
     }
 
     pub fn hard_drop(&mut self) -> ActionInfo {
-        if self.queue.is_empty() {
+        if self.active_unknown {
             return ActionInfo::Fail;
         }
 
@@ -219,6 +329,9 @@ impl Game {
     }
 
     pub fn apply(&mut self, action: Action) -> ActionInfo {
+        if self.active_unknown {
+            return ActionInfo::Fail;
+        }
         match action {
             Action::ShiftLeft => self.active.shift_left(&self.board).into(),
             Action::ShiftRight => self.active.shift_right(&self.board).into(),
@@ -237,10 +350,14 @@ impl Game {
 impl Display for Game {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
         // Board + Active Piece
-        write!(f, "{}", self.board.to_string(Some(&self.active)))?;
+        let active = (!self.active_unknown).then_some(&self.active);
+        write!(f, "{}", self.board.to_string(active))?;
 
         // Current, Hold, and Queue pieces
-        let curr = format!("{}", &self.active);
+        let curr = match active {
+            Some(active) => active.to_string(),
+            None => "?".to_string(),
+        };
         let hold = match &self.hold {
             Some(piece) => {
                 let can_hold = if self.can_hold { "-" } else { "X" };
@@ -259,3 +376,60 @@ impl Display for Game {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Fin;
+
+    #[test]
+    fn test_game_rules() {
+        let mut bag = Bag::new_rng7(0);
+        let mut game = Game::from_bag_with_preview(&mut bag, 5);
+        assert_eq!(game.queue.len(), 5);
+        assert!(matches!(game.apply(Action::HardDrop), ActionInfo::Lock(_)));
+        game.refill_queue(&mut bag);
+        assert_eq!(game.queue.len(), 5);
+
+        assert!(game.children(Fin::Simple1).iter().any(|child| child.hold));
+        game.hold_disabled = true;
+        assert_eq!(game.apply(Action::Hold), ActionInfo::Fail);
+        assert!(game.children(Fin::Simple1).iter().all(|child| !child.hold));
+
+        // Without a preview the next piece is only dealt once it's needed,
+        // and pieces are dealt in the same order
+        let mut bag = Bag::new_rng7(0);
+        let mut game = Game::from_bag_with_preview(&mut bag, 0);
+        let shown = Game::from_bag_with_preview(&mut Bag::new_rng7(0), 2);
+        assert!(game.queue.is_empty());
+        assert!(matches!(game.apply(Action::HardDrop), ActionInfo::Lock(_)));
+        assert!(game.active_unknown);
+        assert_eq!(game.apply(Action::ShiftLeft), ActionInfo::Fail);
+        game.refill_queue(&mut bag);
+        assert!(game.queue.is_empty() && !game.active_unknown);
+        assert_eq!(Some(game.active.piece_type), shown.queue.iter().next());
+        let active = game.active.piece_type;
+        assert!(game.swap_hold());
+        assert!(game.active_unknown);
+        assert_eq!(game.hold, Some(active));
+        game.refill_queue(&mut bag);
+        assert_eq!(Some(game.active.piece_type), shown.queue.iter().nth(1));
+
+        // The unseen piece can't be observed through the game's children:
+        // locking leaves it unknown and holding can't place it
+        let children = game.children(Fin::Simple1);
+        assert!(!children.is_empty());
+        assert!(children
+            .iter()
+            .all(|child| !child.hold && child.game.active_unknown));
+        assert!(game.placements().iter().all(|placement| {
+            placement.actions[0] != Action::Hold && placement.game.active_unknown
+        }));
+
+        // A new game holds nothing but its active piece
+        let game = Game::from_bag_with_preview(&mut Bag::new_rng7(0), 0);
+        let mut expected = Game::from_pieces(game.active.piece_type, None, &[]);
+        expected.preview_len = 0;
+        assert_eq!(game, expected);
+    }
+}
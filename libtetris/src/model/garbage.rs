@@ -0,0 +1,185 @@
+use crate::{Board, Game, LockInfo};
+use rand::Rng;
+use rand_core::SeedableRng;
+use rand_xorshift::XorShiftRng;
+use std::collections::VecDeque;
+
+/// A batch of incoming garbage lines
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Garbage {
+    pub lines: u8,
+    /// Column of the hole in the first row of the batch
+    pub column: u8,
+    /// Number of locks remaining before the garbage is able to rise
+    pub delay: u8,
+}
+
+/// Garbage that has been received but not yet added to the board
+#[derive(Debug, Clone)]
+pub struct GarbageQueue {
+    pending: VecDeque<Garbage>,
+    rng: XorShiftRng,
+    /// Probability that the hole changes column between two rows of the same
+    /// batch, each batch always starts at a random column
+    pub messiness: f32,
+    /// Number of locks before received garbage is able to rise
+    pub delay: u8,
+    /// Maximum number of lines that can rise on a single lock
+    pub cap: u8,
+    /// Width of the board the garbage rises onto
+    width: u8,
+}
+
+impl GarbageQueue {
+    /// Create an empty garbage queue for the given board, garbage rises on
+    /// the next lock that doesn't clear lines
+    pub fn new(seed: u64, board: &Board) -> Self {
+        GarbageQueue {
+            pending: VecDeque::new(),
+            rng: XorShiftRng::seed_from_u64(seed),
+            messiness: 0.,
+            delay: 0,
+            cap: 8,
+            width: board.width() as u8,
+        }
+    }
+
+    /// The batches of garbage waiting to rise, in the order they will rise
+    pub fn pending(&self) -> impl Iterator<Item = &Garbage> {
+        self.pending.iter()
+    }
+
+    /// Total number of pending garbage lines
+    pub fn pending_lines(&self) -> u32 {
+        self.pending
+            .iter()
+            .map(|garbage| garbage.lines as u32)
+            .sum()
+    }
+
+    /// Receive a batch of garbage, the hole is placed in a random column
+    pub fn receive(&mut self, lines: u8) {
+        if lines == 0 {
+            return;
+        }
+        let column = self.rng.random_range(0..self.width);
+        self.pending.push_back(Garbage {
+            lines,
+            column,
+            delay: self.delay,
+        });
+    }
+
+    /// Cancel pending garbage with outgoing attack, oldest garbage first.
+    /// Returns the attack left over after cancelling.
+    pub fn cancel(&mut self, attack: u8) -> u8 {
+        let mut attack = attack;
+        while attack > 0 {
+            let Some(garbage) = self.pending.front_mut() else {
+                break;
+            };
+            let amount = attack.min(garbage.lines);
+            garbage.lines -= amount;
+            attack -= amount;
+            if garbage.lines == 0 {
+                self.pending.pop_front();
+            }
+        }
+        attack
+    }
+
+    /// Update the queue after a piece has been locked in the game. Clears
+    /// cancel pending garbage, other locks let ready garbage rise onto the
+    /// board. Returns the attack left over to be sent to the opponent.
+    ///
+    /// Check `game.board.topped_out()` afterwards, rising garbage can push
+    /// the stack out of the visible area.
+    pub fn on_lock(&mut self, game: &mut Game, lock_info: &LockInfo) -> u8 {
+        let outgoing = if lock_info.lines_cleared > 0 {
+            self.cancel(lock_info.attack)
+        } else {
+            self.rise(game);
+            0
+        };
+        for garbage in self.pending.iter_mut() {
+            garbage.delay = garbage.delay.saturating_sub(1);
+        }
+        outgoing
+    }
+
+    /// Add ready garbage to the board, up to the cap
+    fn rise(&mut self, game: &mut Game) {
+        let mut remaining = self.cap;
+        while remaining > 0 {
+            let Some(garbage) = self.pending.front_mut() else {
+                break;
+            };
+            if garbage.delay > 0 {
+                break;
+            }
+            let amount = remaining.min(garbage.lines);
+            for _ in 0..amount {
+                game.board.add_garbage(garbage.column as usize, 1);
+                if self.rng.random::<f32>() < self.messiness {
+                    let offset = self.rng.random_range(1..self.width);
+                    garbage.column = (garbage.column + offset) % self.width;
+                }
+            }
+            garbage.lines -= amount;
+            remaining -= amount;
+            if garbage.lines == 0 {
+                self.pending.pop_front();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Bag, SpinKind};
+
+    fn lock_info(lines_cleared: u8, attack: u8) -> LockInfo {
+        LockInfo {
+            top_out: false,
+            lines_cleared,
+            spin: SpinKind::None,
+            perfect_clear: false,
+            combo: 0,
+            back_to_back: false,
+            attack,
+        }
+    }
+
+    #[test]
+    fn test_garbage_queue() {
+        let mut game = Game::from_bag(&mut Bag::new_rng7(0));
+        let mut garbage = GarbageQueue::new(0, &game.board);
+        garbage.delay = 1;
+        garbage.receive(3);
+        garbage.receive(2);
+        assert_eq!(garbage.pending_lines(), 5);
+
+        // Clears cancel the oldest garbage first
+        assert_eq!(garbage.on_lock(&mut game, &lock_info(2, 1)), 0);
+        assert_eq!(garbage.pending_lines(), 4);
+
+        // Garbage is delayed, then rises on a lock that doesn't clear
+        garbage.cap = 3;
+        garbage.on_lock(&mut game, &lock_info(0, 0));
+        assert_eq!(game.board.max_height(), 3);
+        assert_eq!(garbage.pending_lines(), 1);
+
+        // Leftover attack is sent out
+        assert_eq!(garbage.on_lock(&mut game, &lock_info(4, 4)), 3);
+        assert_eq!(garbage.pending_lines(), 0);
+
+        // Holes stay inside narrow boards
+        game.set_board(Board::with_size(4, 24, 20).unwrap());
+        let mut garbage = GarbageQueue::new(1, &game.board);
+        garbage.messiness = 1.;
+        garbage.receive(8);
+        garbage.on_lock(&mut game, &lock_info(0, 0));
+        assert_eq!(game.board.max_height(), 8);
+    }
+}
@@ -1,16 +1,28 @@
+mod attack;
 mod bag;
 mod board;
 mod children;
+mod distribution;
+mod finesse;
 mod game;
+mod garbage;
+mod movegen;
 mod piece;
 mod piece_info;
 mod piece_queue;
+mod rotation;
 
 // Re-exports
+pub use attack::*;
 pub use bag::*;
 pub use board::*;
 pub use children::*;
+pub use distribution::*;
+pub use finesse::*;
 pub use game::*;
+pub use garbage::*;
+pub use movegen::*;
 pub use piece::*;
 pub use piece_info::*;
 pub use piece_queue::*;
+pub use rotation::*;
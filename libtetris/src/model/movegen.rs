@@ -0,0 +1,331 @@
+use crate::{
+    Action, ActionInfo, Board, Game, LockInfo, Piece, SpinKind, BOARD_MAX_HEIGHT, BOARD_MAX_WIDTH,
+};
+use fnv::FnvHashMap;
+
+/// Offset added to piece coordinates so that they are never negative
+const OFFSET: i8 = 3;
+const MAP_WIDTH: usize = BOARD_MAX_WIDTH + 4;
+const MAP_HEIGHT: usize = BOARD_MAX_HEIGHT + 4;
+/// Number of distinct `last_kick` values tracked per position, half turns
+/// are tracked apart from quarter turns
+const KICKS: usize = 16;
+pub(crate) const STATES: usize = 4 * KICKS * MAP_WIDTH * MAP_HEIGHT;
+
+/// A reachable lock position, found by `Game::placements`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Placement {
+    /// The game after the piece is locked
+    pub game: Game,
+    /// The piece right before it is locked
+    pub piece: Piece,
+    pub lock_info: LockInfo,
+    /// Shortest list of inputs that reaches the placement, ending in a hard
+    /// drop
+    pub actions: Vec<Action>,
+}
+
+/// For every rotation and x position, a bitmask over y of the positions
+/// where a piece would be out of bounds or intersect the board
+pub(crate) struct CollisionMap {
+    columns: [[u64; MAP_WIDTH]; 4],
+}
+
+impl CollisionMap {
+    pub(crate) fn new(board: &Board, piece: &Piece) -> Self {
+        let mut columns = [[u64::MAX; MAP_WIDTH]; 4];
+        for rotation in 0..4 {
+            let (min_x, max_x, min_y, max_y) =
+                piece
+                    .rotation_system
+                    .location_bound(piece.piece_type, rotation, board);
+            for x in min_x..=max_x {
+                let shape = piece
+                    .rotation_system
+                    .bit_shape(piece.piece_type, rotation, x);
+                let mut column = u64::MAX;
+                for y in min_y..=max_y {
+                    let intersects = (0..4).any(|j| {
+                        let row = y + j as i8;
+                        row >= 0
+                            && (row as usize) < board.height()
+                            && shape[j] & board.matrix[row as usize] != 0
+                    });
+                    if !intersects {
+                        column &= !(1 << (y + OFFSET));
+                    }
+                }
+                columns[rotation as usize][(x + OFFSET) as usize] = column;
+            }
+        }
+        CollisionMap { columns }
+    }
+
+    pub(crate) fn collides(&self, rotation: i8, x: i8, y: i8) -> bool {
+        let (x, y) = (x + OFFSET, y + OFFSET);
+        if x < 0 || x as usize >= MAP_WIDTH || y < 0 || y as usize >= MAP_HEIGHT {
+            return true;
+        }
+        (self.columns[rotation as usize][x as usize] >> y) & 1 != 0
+    }
+
+    /// Move a piece by an offset, returns `None` if it would collide
+    pub(crate) fn shift(&self, piece: &Piece, d_x: i8, d_y: i8) -> Option<Piece> {
+        let (x, y) = (piece.position_x + d_x, piece.position_y + d_y);
+        if self.collides(piece.rotation, x, y) {
+            return None;
+        }
+        Some(Piece {
+            position_x: x,
+            position_y: y,
+            last_kick: None,
+            last_rotation: None,
+            ..*piece
+        })
+    }
+
+    /// Drop a piece as far down as possible, returns `None` if it can't move
+    pub(crate) fn drop(&self, piece: &Piece) -> Option<Piece> {
+        let (x, y) = (piece.position_x, piece.position_y);
+        if self.collides(piece.rotation, x, y - 1) {
+            return None;
+        }
+        let column = self.columns[piece.rotation as usize][(x + OFFSET) as usize];
+        let below = column & ((1 << (y + OFFSET)) - 1);
+        Some(Piece {
+            position_y: (64 - below.leading_zeros()) as i8 - OFFSET,
+            last_kick: None,
+            last_rotation: None,
+            ..*piece
+        })
+    }
+
+    /// Rotate a piece clockwise by `amount` quarter turns, following the
+    /// same kick rules as `Piece::rotate`
+    pub(crate) fn rotate(&self, piece: &Piece, amount: i8, board: &Board) -> Option<Piece> {
+        let rotation_system = piece.rotation_system;
+        let (x, y) = (piece.position_x, piece.position_y);
+        let rotation = (piece.rotation + amount) % 4;
+        let kicks = rotation_system.kick_table(piece.piece_type, piece.rotation, rotation);
+        for (i, &(d_x, d_y)) in kicks.iter().enumerate() {
+            if i == 1 && !rotation_system.can_kick(piece.piece_type, rotation, x, y, board) {
+                break;
+            }
+            if !self.collides(rotation, x + d_x, y + d_y) {
+                return Some(Piece {
+                    position_x: x + d_x,
+                    position_y: y + d_y,
+                    rotation,
+                    last_kick: Some(i as u8),
+                    last_rotation: Some(piece.rotation),
+                    ..*piece
+                });
+            }
+        }
+        None
+    }
+
+    /// Whether a piece is resting on the stack or the floor
+    pub(crate) fn grounded(&self, piece: &Piece) -> bool {
+        self.collides(piece.rotation, piece.position_x, piece.position_y - 1)
+    }
+}
+
+/// Hold, lowest row, filled tiles and spin of a placement
+type PlacementKey = (bool, i8, [u16; 4], SpinKind);
+
+/// A search node, the parent of the root node is itself
+#[derive(Clone, Copy)]
+struct Node {
+    piece: Piece,
+    parent: u32,
+    action: Action,
+}
+
+pub(crate) fn state_index(piece: &Piece) -> usize {
+    let half_turn = piece
+        .last_rotation
+        .is_some_and(|from| (piece.rotation - from).abs() == 2);
+    let kick = piece
+        .last_kick
+        .map_or(0, |kick| kick as usize + 1 + 8 * half_turn as usize);
+    let x = (piece.position_x + OFFSET) as usize;
+    let y = (piece.position_y + OFFSET) as usize;
+    ((piece.rotation as usize * KICKS + kick) * MAP_HEIGHT + y) * MAP_WIDTH + x
+}
+
+impl Game {
+    /// Find every distinct lock position reachable by the active piece (and
+    /// the hold piece), using a breadth-first search over every position,
+    /// rotation and kick. Each placement comes with the shortest sequence of
+    /// inputs that reaches it.
+    pub fn placements(&self) -> Vec<Placement> {
+        let mut output = Vec::new();
+        let mut found = FnvHashMap::default();
+        for hold in [false, true] {
+            let mut game = *self;
+            // Holding into an empty queue leaves no piece to place
+            if hold && (!game.swap_hold() || game.active_unknown) {
+                continue;
+            }
+            game.search_placements(hold, &mut output, &mut found);
+        }
+        output
+    }
+
+    fn search_placements(
+        &self,
+        hold: bool,
+        output: &mut Vec<Placement>,
+        found: &mut FnvHashMap<PlacementKey, usize>,
+    ) {
+        let board = &self.board;
+        let active = self.active;
+        let map = CollisionMap::new(board, &active);
+
+        let mut visited = vec![0u64; STATES.div_ceil(64)];
+        let mut nodes = vec![Node {
+            piece: active,
+            parent: 0,
+            action: Action::HardDrop,
+        }];
+        visited[state_index(&active) / 64] |= 1 << (state_index(&active) % 64);
+
+        let mut head = 0;
+        while head < nodes.len() {
+            let piece = nodes[head].piece;
+
+            // Lock the piece if it is on the ground
+            if map.grounded(&piece) {
+                self.add_placement(&nodes, head, hold, output, found);
+            }
+
+            let moves = [
+                (Action::ShiftLeft, map.shift(&piece, -1, 0)),
+                (Action::ShiftRight, map.shift(&piece, 1, 0)),
+                (Action::ShiftDown, map.shift(&piece, 0, -1)),
+                (Action::SoftDrop, map.drop(&piece)),
+                (Action::RotateCw, map.rotate(&piece, 1, board)),
+                (Action::Rotate180, map.rotate(&piece, 2, board)),
+                (Action::RotateCcw, map.rotate(&piece, 3, board)),
+            ];
+            for (action, piece) in moves {
+                let Some(piece) = piece else {
+                    continue;
+                };
+                let idx = state_index(&piece);
+                if visited[idx / 64] & (1 << (idx % 64)) != 0 {
+                    continue;
+                }
+                visited[idx / 64] |= 1 << (idx % 64);
+                nodes.push(Node {
+                    piece,
+                    parent: head as u32,
+                    action,
+                });
+            }
+            head += 1;
+        }
+    }
+
+    fn add_placement(
+        &self,
+        nodes: &[Node],
+        idx: usize,
+        hold: bool,
+        output: &mut Vec<Placement>,
+        found: &mut FnvHashMap<PlacementKey, usize>,
+    ) {
+        let piece = nodes[idx].piece;
+        let mut game = *self;
+        game.active = piece;
+        let ActionInfo::Lock(lock_info) = game.lock() else {
+            return;
+        };
+
+        // Placements are distinct by the tiles they fill and their spin
+        let shape =
+            piece
+                .rotation_system
+                .bit_shape(piece.piece_type, piece.rotation, piece.position_x);
+        let low = shape.iter().position(|&row| row != 0).unwrap_or(0);
+        let mut tiles = [0; 4];
+        tiles[..4 - low].copy_from_slice(&shape[low..]);
+        let key = (hold, piece.position_y + low as i8, tiles, lock_info.spin);
+
+        let mut actions = Vec::new();
+        let mut i = idx;
+        while i != 0 {
+            actions.push(nodes[i].action);
+            i = nodes[i].parent as usize;
+        }
+        if hold {
+            actions.push(Action::Hold);
+        }
+        actions.reverse();
+        // A hard drop already drops the piece to the bottom
+        if actions.last() == Some(&Action::SoftDrop) {
+            actions.pop();
+        }
+        actions.push(Action::HardDrop);
+
+        let placement = Placement {
+            game,
+            piece,
+            lock_info,
+            actions,
+        };
+        match found.get(&key) {
+            Some(&i) if output[i].actions.len() <= placement.actions.len() => {}
+            Some(&i) => output[i] = placement,
+            None => {
+                found.insert(key, output.len());
+                output.push(placement);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Fin, PieceType};
+
+    #[test]
+    fn test_placements() {
+        // An overhang that can only be filled by tucking under it
+        let mut board = Board::new();
+        board.matrix[0] = 0b1100000000;
+        board.matrix[1] = 0b1100000000;
+        board.matrix[2] = 0b0000111111;
+        let mut game = Game::from_pieces(PieceType::O, None, &[PieceType::T, PieceType::I]);
+        game.set_board(board);
+
+        let placements = game.placements();
+        let tuck = placements
+            .iter()
+            .find(|placement| {
+                !placement.actions.contains(&Action::Hold)
+                    && placement.game.board.matrix[0] == 0b1100000011
+                    && placement.game.board.matrix[1] == 0b1100000011
+            })
+            .unwrap();
+        assert!(tuck.actions.contains(&Action::SoftDrop));
+
+        for placement in placements.iter() {
+            let mut new_game = game;
+            let mut info = ActionInfo::Fail;
+            for &action in placement.actions.iter() {
+                info = new_game.apply(action);
+            }
+            assert_eq!(new_game, placement.game);
+            assert_eq!(info, ActionInfo::Lock(placement.lock_info));
+        }
+        for child in game.children(Fin::Full3) {
+            assert!(placements
+                .iter()
+                .any(|placement| placement.game == child.game
+                    && placement.lock_info == child.lock_info));
+        }
+    }
+}
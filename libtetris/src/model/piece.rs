@@ -1,5 +1,5 @@
 use super::board::Board;
-use crate::PieceInfo;
+use crate::RotationSystem;
 use anyhow::{anyhow, Result};
 use serde::{Deserialize, Serialize};
 use std::fmt::{self, Display, Formatter};
@@ -74,6 +74,16 @@ pub struct Piece {
     pub rotation: i8,
     pub position_x: i8,
     pub position_y: i8,
+    /// Index of the kick used if the last successful move was a rotation,
+    /// None if it was a shift or drop
+    #[serde(default)]
+    pub last_kick: Option<u8>,
+    /// Rotation before the last successful move if it was a rotation, None
+    /// if it was a shift or drop
+    #[serde(default)]
+    pub last_rotation: Option<i8>,
+    #[serde(default)]
+    pub rotation_system: RotationSystem,
 }
 
 impl Piece {
@@ -83,22 +93,38 @@ impl Piece {
             rotation,
             position_x,
             position_y,
+            last_kick: None,
+            last_rotation: None,
+            rotation_system: RotationSystem::default(),
         }
     }
 
+    /// Spawn a piece on the default board
     pub fn from_piece_type(piece_type: PieceType) -> Self {
-        let (position_x, position_y) = PieceInfo::spawn_location(piece_type);
+        Piece::spawn(piece_type, RotationSystem::default(), &Board::new())
+    }
+
+    /// Spawn a piece on the given board
+    pub fn spawn(piece_type: PieceType, rotation_system: RotationSystem, board: &Board) -> Self {
+        let (position_x, position_y) = rotation_system.spawn_location(piece_type, board);
         Piece {
             piece_type,
             rotation: 0,
             position_x,
             position_y,
+            last_kick: None,
+            last_rotation: None,
+            rotation_system,
         }
     }
 
-    pub fn reset(&mut self) {
+    /// Move the piece back to its spawn location on the given board
+    pub fn reset(&mut self, board: &Board) {
         self.rotation = 0;
-        (self.position_x, self.position_y) = PieceInfo::spawn_location(self.piece_type);
+        (self.position_x, self.position_y) =
+            self.rotation_system.spawn_location(self.piece_type, board);
+        self.last_kick = None;
+        self.last_rotation = None;
     }
 
     pub fn rotate(&mut self, amount: i8, board: &Board) -> bool {
@@ -108,10 +134,14 @@ impl Piece {
         let new_rot = (self.rotation + amount) % 4;
         self.rotation = new_rot;
 
-        let kick_table = PieceInfo::kick_table(self.piece_type, old_rot, new_rot);
+        let rotation_system = self.rotation_system;
+        let kick_table = rotation_system.kick_table(self.piece_type, old_rot, new_rot);
         let (b_left, b_right, b_bottom, b_top) =
-            PieceInfo::location_bound(self.piece_type, new_rot);
-        for (d_x, d_y) in kick_table {
+            rotation_system.location_bound(self.piece_type, new_rot, board);
+        for (i, (d_x, d_y)) in kick_table.iter().enumerate() {
+            if i == 1 && !rotation_system.can_kick(self.piece_type, new_rot, old_x, old_y, board) {
+                break;
+            }
             let new_x = old_x + d_x;
             let new_y = old_y + d_y;
             self.position_x = new_x;
@@ -120,6 +150,8 @@ impl Piece {
             if !(new_x < b_left || new_x > b_right || new_y < b_bottom || new_y > b_top)
                 && !board.intersects_with(self)
             {
+                self.last_kick = Some(i as u8);
+                self.last_rotation = Some(old_rot);
                 return true;
             }
         }
@@ -150,7 +182,8 @@ impl Piece {
         self.position_y = new_y;
 
         let (b_left, b_right, b_bottom, b_top) =
-            PieceInfo::location_bound(self.piece_type, self.rotation);
+            self.rotation_system
+                .location_bound(self.piece_type, self.rotation, board);
         if new_x < b_left
             || new_x > b_right
             || new_y < b_bottom
@@ -162,6 +195,8 @@ impl Piece {
             return false;
         }
 
+        self.last_kick = None;
+        self.last_rotation = None;
         true
     }
 
@@ -192,6 +227,8 @@ impl Piece {
         }
         // Keep shifting down while possible
         while self.shift_down(board) {}
+        self.last_kick = None;
+        self.last_rotation = None;
         true
     }
 }
@@ -26,7 +26,7 @@ impl PieceQueue {
         }
 
         for i in 0..len {
-            let bits = (queue >> (i * 3)) as u8;
+            let bits = ((queue >> (i * 3)) & 0b111) as u8;
             if PieceType::from_u8(bits).is_err() {
                 bail!("encountered invalid piece bits");
             }
@@ -124,5 +124,14 @@ mod test {
             len += 1;
         }
         assert!(len == PieceType::ALL.len());
+
+        // Each piece is validated on its own bits, not the pieces after it
+        assert_eq!(
+            PieceQueue::from_parts(queue.len, queue.queue).unwrap(),
+            queue
+        );
+        let invalid = queue.queue | 0b111 << 3;
+        assert!(PieceQueue::from_parts(queue.len, invalid).is_err());
+        assert!(PieceQueue::from_parts(queue.len - 1, queue.queue).is_err());
     }
 }
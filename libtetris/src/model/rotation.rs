@@ -0,0 +1,291 @@
+use crate::{Board, PieceInfo, PieceType, BOARD_HEIGHT, BOARD_VISIBLE_HEIGHT, BOARD_WIDTH};
+use serde::{Deserialize, Serialize};
+
+/// The rules used to spawn and rotate pieces
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RotationSystem {
+    /// Guideline SRS, 180 rotations do not kick
+    #[default]
+    Srs,
+    /// Tetrio's SRS+, with symmetric I kicks and 180 kicks
+    SrsPlus,
+    /// Arika rotation system (TGM), pieces rotate inside a 3x3 box and only
+    /// kick one tile left or right
+    Ars,
+}
+
+impl RotationSystem {
+    /// The initial coordinates for each piece, pieces spawn centered just
+    /// above the visible area of the board
+    pub fn spawn_location(self, piece_type: PieceType, board: &Board) -> (i8, i8) {
+        let (x, y) = match self {
+            RotationSystem::Srs | RotationSystem::SrsPlus => PieceInfo::spawn_location(piece_type),
+            RotationSystem::Ars => match piece_type {
+                PieceType::I => (3, 19),
+                _ => (3, 21),
+            },
+        };
+        let offset_x = (board.width() as i8 - BOARD_WIDTH as i8) / 2;
+        let offset_y = board.visible_height() as i8 - BOARD_VISIBLE_HEIGHT as i8;
+        (x + offset_x, y + offset_y)
+    }
+
+    /// The shape of each piece, as a 2d array of bools indexed by [x][y]
+    pub fn shape(self, piece_type: PieceType, rotation: i8) -> [[bool; 4]; 4] {
+        match self {
+            RotationSystem::Srs | RotationSystem::SrsPlus => PieceInfo::shape(piece_type, rotation),
+            RotationSystem::Ars => {
+                let bits = self.bit_shape(piece_type, rotation, 0);
+                let mut shape = [[false; 4]; 4];
+                for (x, column) in shape.iter_mut().enumerate() {
+                    for (y, tile) in column.iter_mut().enumerate() {
+                        *tile = bits[y] & (1 << x) != 0;
+                    }
+                }
+                shape
+            }
+        }
+    }
+
+    /// u16 bitmask of each shape, if shifting out of bounds, the shape will be cut-off
+    pub fn bit_shape(self, piece_type: PieceType, rotation: i8, x: i8) -> [u16; 4] {
+        let arr = match self {
+            RotationSystem::Srs | RotationSystem::SrsPlus => {
+                return PieceInfo::bit_shape(piece_type, rotation, x)
+            }
+            RotationSystem::Ars => match piece_type {
+                PieceType::O => [[6, 6, 0, 0], [6, 6, 0, 0], [6, 6, 0, 0], [6, 6, 0, 0]],
+                PieceType::I => [[0, 0, 15, 0], [4, 4, 4, 4], [0, 0, 15, 0], [4, 4, 4, 4]],
+                PieceType::T => [[2, 7, 0, 0], [2, 3, 2, 0], [7, 2, 0, 0], [2, 6, 2, 0]],
+                PieceType::L => [[1, 7, 0, 0], [2, 2, 3, 0], [7, 4, 0, 0], [6, 2, 2, 0]],
+                PieceType::J => [[4, 7, 0, 0], [3, 2, 2, 0], [7, 1, 0, 0], [2, 2, 6, 0]],
+                PieceType::S => [[3, 6, 0, 0], [2, 3, 1, 0], [3, 6, 0, 0], [2, 3, 1, 0]],
+                PieceType::Z => [[6, 3, 0, 0], [2, 6, 4, 0], [6, 3, 0, 0], [2, 6, 4, 0]],
+            },
+        };
+        let mut bits = arr[rotation as usize];
+        if x < 0 {
+            for row in bits.iter_mut() {
+                *row >>= -x;
+            }
+        } else {
+            for row in bits.iter_mut() {
+                *row <<= x;
+            }
+        }
+        bits
+    }
+
+    /// Bounds of location coordinates for each piece on the given board
+    /// (min x, max x, min y, max y)
+    pub fn location_bound(
+        self,
+        piece_type: PieceType,
+        rotation: i8,
+        board: &Board,
+    ) -> (i8, i8, i8, i8) {
+        let (min_x, max_x, min_y, max_y) = self.default_location_bound(piece_type, rotation);
+        let offset_x = board.width() as i8 - BOARD_WIDTH as i8;
+        let offset_y = board.height() as i8 - BOARD_HEIGHT as i8;
+        (min_x, max_x + offset_x, min_y, max_y + offset_y)
+    }
+
+    /// Bounds of location coordinates on a default 10x24 board
+    fn default_location_bound(self, piece_type: PieceType, rotation: i8) -> (i8, i8, i8, i8) {
+        let arr = match self {
+            RotationSystem::Srs | RotationSystem::SrsPlus => {
+                return PieceInfo::location_bound(piece_type, rotation)
+            }
+            RotationSystem::Ars => match piece_type {
+                PieceType::O => [
+                    (-1, 7, 0, 22),
+                    (-1, 7, 0, 22),
+                    (-1, 7, 0, 22),
+                    (-1, 7, 0, 22),
+                ],
+                PieceType::I => [
+                    (0, 6, -2, 21),
+                    (-2, 7, 0, 20),
+                    (0, 6, -2, 21),
+                    (-2, 7, 0, 20),
+                ],
+                PieceType::T => [(0, 7, 0, 22), (0, 8, 0, 21), (0, 7, 0, 22), (-1, 7, 0, 21)],
+                PieceType::L => [(0, 7, 0, 22), (0, 8, 0, 21), (0, 7, 0, 22), (-1, 7, 0, 21)],
+                PieceType::J => [(0, 7, 0, 22), (0, 8, 0, 21), (0, 7, 0, 22), (-1, 7, 0, 21)],
+                PieceType::S => [(0, 7, 0, 22), (0, 8, 0, 21), (0, 7, 0, 22), (0, 8, 0, 21)],
+                PieceType::Z => [(0, 7, 0, 22), (-1, 7, 0, 21), (0, 7, 0, 22), (-1, 7, 0, 21)],
+            },
+        };
+        arr[rotation as usize]
+    }
+
+    /// Possible (x, y) shifts when doing kicks
+    pub fn kick_table(
+        self,
+        piece_type: PieceType,
+        old_rotation: i8,
+        new_rotation: i8,
+    ) -> &'static [(i8, i8)] {
+        match self {
+            RotationSystem::Srs => PieceInfo::kick_table(piece_type, old_rotation, new_rotation),
+            RotationSystem::SrsPlus => {
+                if old_rotation == new_rotation || piece_type == PieceType::O {
+                    PieceInfo::kick_table(piece_type, old_rotation, new_rotation)
+                } else if (old_rotation - new_rotation).abs() == 2 {
+                    SRS_PLUS_180_KICKS[old_rotation as usize]
+                } else if piece_type == PieceType::I {
+                    SRS_PLUS_I_KICKS[old_rotation as usize][new_rotation as usize]
+                } else {
+                    PieceInfo::kick_table(piece_type, old_rotation, new_rotation)
+                }
+            }
+            RotationSystem::Ars => match piece_type {
+                _ if old_rotation == new_rotation => &[],
+                PieceType::O | PieceType::I => &[(0, 0)],
+                _ => &[(0, 0), (1, 0), (-1, 0)],
+            },
+        }
+    }
+
+    /// Whether a blocked rotation is allowed to try the remaining kicks.
+    /// `x` and `y` are the position of the piece before any kick is applied.
+    ///
+    /// In ARS, L, J and T pieces may not kick if the first blocked tile
+    /// (reading from the top left of the 3x3 box) is in the center column.
+    pub fn can_kick(
+        self,
+        piece_type: PieceType,
+        rotation: i8,
+        x: i8,
+        y: i8,
+        board: &Board,
+    ) -> bool {
+        if self != RotationSystem::Ars
+            || !matches!(piece_type, PieceType::L | PieceType::J | PieceType::T)
+        {
+            return true;
+        }
+        let bits = self.bit_shape(piece_type, rotation, 0);
+        for d_y in (0..3).rev() {
+            for d_x in 0..3 {
+                if bits[d_y as usize] & (1 << d_x) == 0 {
+                    continue;
+                }
+                let (t_x, t_y) = (x + d_x, y + d_y);
+                let blocked = if t_x < 0 || t_x >= board.width() as i8 || t_y < 0 {
+                    true
+                } else if t_y >= board.height() as i8 {
+                    false
+                } else {
+                    board.get(t_x as usize, t_y as usize)
+                };
+                if blocked {
+                    return d_x != 1;
+                }
+            }
+        }
+        true
+    }
+
+    /// Whether a t-spin using this kick always counts as a full t-spin. This
+    /// is the last kick of a quarter turn in SRS and SRS+, used for tst and
+    /// fin setups.
+    pub fn is_tst_kick(self, from: i8, to: i8, kick: u8) -> bool {
+        matches!(self, RotationSystem::Srs | RotationSystem::SrsPlus)
+            && (to - from).abs() % 2 == 1
+            && kick == 4
+    }
+
+    /// Corners of the 3x3 box around a T piece, relative to its position.
+    /// The first two are the corners in front of the T (the side it points
+    /// towards).
+    pub fn t_corners(self, rotation: i8) -> [(i8, i8); 4] {
+        match (self, rotation) {
+            (RotationSystem::Srs | RotationSystem::SrsPlus, 0) => [(0, 2), (2, 2), (0, 0), (2, 0)],
+            (RotationSystem::Srs | RotationSystem::SrsPlus, 1) => [(2, 2), (2, 0), (0, 2), (0, 0)],
+            (RotationSystem::Srs | RotationSystem::SrsPlus, 2) => [(0, 0), (2, 0), (0, 2), (2, 2)],
+            (RotationSystem::Srs | RotationSystem::SrsPlus, 3) => [(0, 0), (0, 2), (2, 0), (2, 2)],
+            (RotationSystem::Ars, 0) => [(0, 0), (2, 0), (0, 2), (2, 2)],
+            (RotationSystem::Ars, 1) => [(0, 0), (0, 2), (2, 0), (2, 2)],
+            (RotationSystem::Ars, 2) => [(0, 1), (2, 1), (0, -1), (2, -1)],
+            (RotationSystem::Ars, 3) => [(2, 2), (2, 0), (0, 2), (0, 0)],
+            _ => panic!("invalid rotation"),
+        }
+    }
+}
+
+/// SRS+ kicks for 180 rotations, indexed by the starting rotation
+const SRS_PLUS_180_KICKS: [&[(i8, i8)]; 4] = [
+    &[(0, 0), (0, 1), (1, 1), (-1, 1), (1, 0), (-1, 0)],
+    &[(0, 0), (1, 0), (1, 2), (1, 1), (0, 2), (0, 1)],
+    &[(0, 0), (0, -1), (-1, -1), (1, -1), (-1, 0), (1, 0)],
+    &[(0, 0), (-1, 0), (-1, 2), (-1, 1), (0, 2), (0, 1)],
+];
+
+/// SRS+ kicks for the I piece, mirrored so that clockwise and
+/// counter-clockwise rotations behave the same
+const SRS_PLUS_I_KICKS: [[&[(i8, i8)]; 4]; 4] = [
+    [
+        &[],
+        &[(0, 0), (1, 0), (-2, 0), (-2, -1), (1, 2)],
+        &[(0, 0)],
+        &[(0, 0), (-1, 0), (2, 0), (2, -1), (-1, 2)],
+    ],
+    [
+        &[(0, 0), (-1, 0), (2, 0), (-1, -2), (2, 1)],
+        &[],
+        &[(0, 0), (-1, 0), (2, 0), (-1, 2), (2, -1)],
+        &[(0, 0)],
+    ],
+    [
+        &[(0, 0)],
+        &[(0, 0), (-2, 0), (1, 0), (-2, 1), (1, -2)],
+        &[],
+        &[(0, 0), (2, 0), (-1, 0), (2, 1), (-1, -2)],
+    ],
+    [
+        &[(0, 0), (1, 0), (-2, 0), (1, -2), (-2, 1)],
+        &[(0, 0)],
+        &[(0, 0), (1, 0), (-2, 0), (1, 2), (-2, -1)],
+        &[],
+    ],
+];
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Piece;
+
+    #[test]
+    fn test_rotation_systems() {
+        let board = Board::new();
+
+        // Only SRS+ can kick a 180 off the floor
+        let mut piece = Piece::from_parts(PieceType::T, 0, 3, -1);
+        assert!(!piece.rotate_180(&board));
+        piece.rotation_system = RotationSystem::SrsPlus;
+        assert!(piece.rotate_180(&board));
+        assert_eq!((piece.position_x, piece.position_y), (3, 0));
+
+        // ARS pieces spawn flat side up
+        let mut piece = Piece::spawn(PieceType::T, RotationSystem::Ars, &board);
+        assert!(piece.soft_drop(&board));
+        let mut locked = board;
+        locked.lock(&piece, Default::default());
+        assert_eq!(locked.matrix[..2], [0b10000, 0b111000]);
+
+        // ARS kicks are blocked by the center column
+        let mut piece = Piece::spawn(PieceType::T, RotationSystem::Ars, &board);
+        (piece.position_x, piece.position_y) = (3, 1);
+        let mut blocked = board;
+        blocked.set(4, 3, true);
+        assert!(!piece.rotate_cw(&blocked));
+
+        piece.rotate_cw(&board);
+        let mut blocked = board;
+        blocked.set(5, 1, true);
+        assert!(piece.rotate_cw(&blocked));
+        assert_eq!((piece.position_x, piece.last_kick), (2, Some(2)));
+    }
+}
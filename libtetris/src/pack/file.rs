@@ -0,0 +1,223 @@
+use super::{Pack, PackBuffer, PackCursor, STREAM_CHUNK};
+use anyhow::{bail, Result};
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, ErrorKind, Read, Write},
+    path::Path,
+};
+
+/// Magic bytes at the start of every pack file
+pub const PACK_MAGIC: [u8; 4] = *b"TPAK";
+
+/// Version of the pack file header
+pub const PACK_FORMAT_VERSION: u16 = 1;
+
+/// Magic, format version, tag, payload version, payload length and checksum
+const HEADER_LEN: usize = 4 + 2 + 4 + 2 + 8 + 4;
+
+/// Describes a kind of file holding a single packed value. The payload is
+/// preceded by a header with the magic bytes, the header format version, a
+/// tag identifying what is stored, the version of the payload format, the
+/// payload length and its CRC-32 checksum, all little endian.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PackFile {
+    pub tag: [u8; 4],
+    /// Bump this whenever the packed layout of the stored type changes, so
+    /// that stale files are rejected instead of decoding to garbage
+    pub version: u16,
+}
+
+impl PackFile {
+    pub const fn new(tag: [u8; 4], version: u16) -> Self {
+        PackFile { tag, version }
+    }
+
+    fn name(&self) -> String {
+        String::from_utf8_lossy(&self.tag).into_owned()
+    }
+
+    fn header(&self, len: u64, crc: u32) -> Vec<u8> {
+        let mut buf = PackBuffer::new();
+        buf.write(&PACK_MAGIC);
+        buf.write_u16(PACK_FORMAT_VERSION);
+        buf.write(&self.tag);
+        buf.write_u16(self.version);
+        buf.write_u64(len);
+        buf.write_u32(crc);
+        buf.buf
+    }
+
+    /// Pack a value into memory with a header
+    pub fn pack<T: Pack>(&self, value: &T) -> Vec<u8> {
+        let payload = value.pack_bytes();
+        let mut crc = Crc32::new();
+        crc.update(&payload);
+        let mut bytes = self.header(payload.len() as u64, crc.finish());
+        bytes.extend(payload);
+        bytes
+    }
+
+    pub fn unpack<T: Pack>(&self, bytes: &[u8]) -> Result<T> {
+        self.read(&mut &bytes[..])
+    }
+
+    /// Stream a value to a writer without holding the packed bytes in
+    /// memory. The value is packed twice, first to measure the header.
+    pub fn write<T: Pack>(&self, value: &T, writer: &mut dyn Write) -> Result<()> {
+        let mut sink = io::sink();
+        let mut buf = PackBuffer::from_writer(&mut sink);
+        value.pack(&mut buf);
+        let (len, crc) = buf.finish()?;
+
+        writer.write_all(&self.header(len, crc))?;
+        let mut buf = PackBuffer::from_writer(writer);
+        value.pack(&mut buf);
+        if buf.finish()? != (len, crc) {
+            bail!("packing {} file is not deterministic", self.name());
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Stream a value from a reader, the payload is read in chunks as it is
+    /// unpacked
+    pub fn read<T: Pack>(&self, reader: &mut dyn Read) -> Result<T> {
+        let name = self.name();
+        let mut header = [0; HEADER_LEN];
+        match reader.read_exact(&mut header) {
+            Err(err) if err.kind() == ErrorKind::UnexpectedEof => {
+                bail!("{name} file is too short to have a header, it may have been written by an older generator");
+            }
+            result => result?,
+        }
+        let mut cur = PackCursor::new(&header);
+        if cur.read_array::<4>()? != PACK_MAGIC {
+            bail!("{name} file has no header, it may have been written by an older generator");
+        }
+        let format_version = cur.read_u16()?;
+        if format_version != PACK_FORMAT_VERSION {
+            bail!(
+                "unsupported pack format version {format_version}, expected {PACK_FORMAT_VERSION}"
+            );
+        }
+        let tag = cur.read_array::<4>()?;
+        if tag != self.tag {
+            bail!(
+                "expected a {name} file, got a {} file",
+                String::from_utf8_lossy(&tag)
+            );
+        }
+        let version = cur.read_u16()?;
+        if version != self.version {
+            bail!(
+                "{name} file has version {version}, expected {}, it needs to be regenerated",
+                self.version
+            );
+        }
+        let len = cur.read_u64()?;
+        let crc = cur.read_u32()?;
+
+        let mut cur = PackCursor::from_reader(reader, len);
+        let value = T::unpack(&mut cur);
+        let unread = cur.len();
+        // Take the rest of the payload so that the whole of it is checked
+        while !cur.finished() {
+            cur.read(cur.len().min(STREAM_CHUNK))?;
+        }
+        if cur.crc.finish() != crc {
+            bail!("{name} file checksum does not match, the file is corrupted");
+        }
+        let value = value?;
+        if unread != 0 {
+            bail!("{name} file has {unread} unread bytes after the payload");
+        }
+        Ok(value)
+    }
+
+    pub fn save<T: Pack>(&self, value: &T, path: impl AsRef<Path>) -> Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write(value, &mut writer)
+    }
+
+    pub fn open<T: Pack>(&self, path: impl AsRef<Path>) -> Result<T> {
+        let mut reader = BufReader::new(File::open(path)?);
+        self.read(&mut reader)
+    }
+}
+
+/// Lookup table for the reflected CRC-32 polynomial used by zip and png
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut j = 0;
+        while j < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB88320
+            } else {
+                crc >> 1
+            };
+            j += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// A running CRC-32 checksum
+#[derive(Debug, Clone, Copy)]
+pub(super) struct Crc32(u32);
+
+impl Crc32 {
+    pub fn new() -> Self {
+        Crc32(!0)
+    }
+
+    pub fn update(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = CRC_TABLE[((self.0 ^ byte as u32) & 0xFF) as usize] ^ (self.0 >> 8);
+        }
+    }
+
+    pub fn finish(&self) -> u32 {
+        !self.0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_pack_file() {
+        let mut crc = Crc32::new();
+        crc.update(b"123456789");
+        assert_eq!(crc.finish(), 0xCBF43926);
+
+        let values = (0..20_000u64).map(|x| x * x).collect::<Vec<_>>();
+        let file = PackFile::new(*b"TEST", 1);
+        let bytes = file.pack(&values);
+        assert!(bytes.len() > 2 * STREAM_CHUNK);
+        let mut written = Vec::new();
+        file.write(&values, &mut written).unwrap();
+        assert_eq!(written, bytes);
+        assert_eq!(file.unpack::<Vec<u64>>(&bytes).unwrap(), values);
+
+        let error = |bytes: &[u8]| file.unpack::<Vec<u64>>(bytes).unwrap_err().to_string();
+        assert!(error(&values.pack_bytes()).contains("older generator"));
+        assert!(error(&bytes[..bytes.len() - 1]).contains("truncated"));
+        assert!(PackFile::new(*b"GAME", 1)
+            .unpack::<Vec<u64>>(&bytes)
+            .is_err());
+        assert!(PackFile::new(*b"TEST", 2)
+            .unpack::<Vec<u64>>(&bytes)
+            .unwrap_err()
+            .to_string()
+            .contains("regenerated"));
+        let mut corrupted = bytes.clone();
+        corrupted[HEADER_LEN + STREAM_CHUNK + 7] ^= 1;
+        assert!(error(&corrupted).contains("corrupted"));
+    }
+}
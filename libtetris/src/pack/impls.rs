@@ -1,4 +1,8 @@
-use crate::{Board, Game, Pack, PackBuffer, PackCursor, Piece, PieceQueue, PieceType};
+use crate::{
+    Action, ActionInfo, AttackTable, BagKind, Board, Game, LockInfo, Pack, PackBuffer, PackCursor,
+    Piece, PieceQueue, PieceType, Replay, ReplayAction, RotationSystem, SpinKind, SpinMode,
+    BOARD_HEIGHT, BOARD_VISIBLE_HEIGHT, BOARD_WIDTH, PIECE_QUEUE_MAX_LEN,
+};
 use anyhow::{bail, Result};
 use std::{collections::HashMap, hash::Hash};
 
@@ -77,14 +81,22 @@ where
 }
 
 impl Pack for PieceQueue {
+    // Writes the length followed by 3 bits per piece
     fn pack(&self, buf: &mut PackBuffer) {
         buf.write_u8(self.len);
-        buf.write_u64(self.queue);
+        buf.write_bits((0..self.len()).map(|i| self.get(i).to_u8() as u64), 3);
     }
 
     fn unpack(cur: &mut PackCursor) -> Result<Self> {
         let len = cur.read_u8()?;
-        let queue = cur.read_u64()?;
+        if len as usize > PIECE_QUEUE_MAX_LEN {
+            bail!("queue is too long");
+        }
+        let queue = cur
+            .read_bits(len as usize, 3)?
+            .into_iter()
+            .enumerate()
+            .fold(0, |queue, (i, bits)| queue | bits << (i * 3));
         PieceQueue::from_parts(len, queue)
     }
 }
@@ -100,55 +112,174 @@ impl Pack for PieceType {
 }
 
 impl Pack for Piece {
+    // The piece type, rotation and rotation system share the first byte, and
+    // the last kick shares the last byte with the rotation before it
     fn pack(&self, buf: &mut PackBuffer) {
-        self.piece_type.pack(buf);
-        buf.write_u8(self.rotation as u8);
+        let rotation_system = match self.rotation_system {
+            RotationSystem::Srs => 0,
+            RotationSystem::SrsPlus => 1,
+            RotationSystem::Ars => 2,
+        };
+        buf.write_u8(self.piece_type.to_u8() | (self.rotation as u8) << 3 | rotation_system << 5);
         buf.write_u8(self.position_x as u8);
         buf.write_u8(self.position_y as u8);
+        let last_kick = match (self.last_kick, self.last_rotation) {
+            (None, _) => 255,
+            (Some(kick), None) => kick,
+            (Some(kick), Some(rotation)) => kick | (0b100 | rotation as u8) << 4,
+        };
+        buf.write_u8(last_kick);
     }
 
     fn unpack(cur: &mut PackCursor) -> Result<Self> {
-        let piece_type = PieceType::unpack(cur)?;
-        let rotation = cur.read_u8()? as i8;
+        let byte = cur.read_u8()?;
+        let piece_type = PieceType::from_u8(byte & 0b111)?;
+        let rotation = ((byte >> 3) & 0b11) as i8;
+        let rotation_system = match byte >> 5 {
+            0 => RotationSystem::Srs,
+            1 => RotationSystem::SrsPlus,
+            2 => RotationSystem::Ars,
+            x => bail!("unknown rotation system {x}"),
+        };
         let position_x = cur.read_u8()? as i8;
         let position_y = cur.read_u8()? as i8;
+        let (last_kick, last_rotation) = match cur.read_u8()? {
+            255 => (None, None),
+            x if x >> 4 == 0 => (Some(x), None),
+            x if x >> 6 == 1 => (Some(x & 0b1111), Some((x >> 4 & 0b11) as i8)),
+            x => bail!("invalid last kick {x}"),
+        };
         Ok(Piece {
             piece_type,
             rotation,
             position_x,
             position_y,
+            last_kick,
+            last_rotation,
+            rotation_system,
         })
     }
 }
 
 impl Pack for Board {
-    // Writes board state as 24 * 10 = 240 bits (30 bytes)
-    fn pack(&self, buf: &mut PackBuffer) {
-        for i in 0..6 {
-            // Write 4 rows -> 5 bytes
-            let mut accum: u64 = 0;
-            for j in 0..4 {
-                let row = self.matrix[i * 4 + j] as u64;
-                accum |= row << (j * 10);
-            }
-            buf.write_packed(accum, 5);
+    // Writes the board size, or a single 0 byte for the default size,
+    // followed by the number of rows up to the highest filled row and
+    // `width` bits for each of those rows
+    fn pack(&self, buf: &mut PackBuffer) {
+        let width = self.width();
+        if (width, self.height(), self.visible_height())
+            == (BOARD_WIDTH, BOARD_HEIGHT, BOARD_VISIBLE_HEIGHT)
+        {
+            buf.write_u8(0);
+        } else {
+            buf.write_u8(width as u8);
+            buf.write_u8(self.height() as u8);
+            buf.write_u8(self.visible_height() as u8);
         }
+        let rows = self.matrix[..self.height()]
+            .iter()
+            .rposition(|&row| row != 0)
+            .map_or(0, |y| y + 1);
+        buf.write_u8(rows as u8);
+        buf.write_bits(self.matrix[..rows].iter().map(|&row| row as u64), width);
     }
 
     fn unpack(cur: &mut PackCursor) -> Result<Self> {
-        let mut board = Board::new();
-        for i in 0..6 {
-            let accum = cur.read_packed(5)?;
-            for j in 0..4 {
-                let row = ((accum >> (j * 10)) & ((1 << 10) - 1)) as u16;
-                board.set_row(i * 4 + j, row);
+        let mut board = match cur.read_u8()? {
+            0 => Board::new(),
+            width => {
+                let height = cur.read_u8()? as usize;
+                let visible_height = cur.read_u8()? as usize;
+                Board::with_size(width as usize, height, visible_height)?
             }
+        };
+        let rows = cur.read_u8()? as usize;
+        if rows > board.height() {
+            bail!("board has {rows} rows, expected at most {}", board.height());
+        }
+        for (y, row) in cur.read_bits(rows, board.width())?.into_iter().enumerate() {
+            board.set_row(y, row as u16);
         }
         Ok(board)
     }
 }
 
+impl Pack for RotationSystem {
+    fn pack(&self, buf: &mut PackBuffer) {
+        buf.write_u8(match self {
+            RotationSystem::Srs => 0,
+            RotationSystem::SrsPlus => 1,
+            RotationSystem::Ars => 2,
+        });
+    }
+
+    fn unpack(cur: &mut PackCursor) -> Result<Self> {
+        match cur.read_u8()? {
+            0 => Ok(RotationSystem::Srs),
+            1 => Ok(RotationSystem::SrsPlus),
+            2 => Ok(RotationSystem::Ars),
+            x => bail!("unknown rotation system {x}"),
+        }
+    }
+}
+
+impl Pack for SpinMode {
+    fn pack(&self, buf: &mut PackBuffer) {
+        buf.write_u8(match self {
+            SpinMode::TSpin => 0,
+            SpinMode::AllSpin => 1,
+        });
+    }
+
+    fn unpack(cur: &mut PackCursor) -> Result<Self> {
+        match cur.read_u8()? {
+            0 => Ok(SpinMode::TSpin),
+            1 => Ok(SpinMode::AllSpin),
+            x => bail!("unknown spin mode {x}"),
+        }
+    }
+}
+
+impl Pack for AttackTable {
+    fn pack(&self, buf: &mut PackBuffer) {
+        buf.write(&self.clear);
+        buf.write(&self.tspin);
+        buf.write(&self.tspin_mini);
+        buf.write_u8(self.back_to_back);
+        buf.write(&self.combo);
+        buf.write_u8(self.combo_multiplier as u8);
+        buf.write_u8(self.perfect_clear);
+    }
+
+    fn unpack(cur: &mut PackCursor) -> Result<Self> {
+        let clear = cur.read_array()?;
+        let tspin = cur.read_array()?;
+        let tspin_mini = cur.read_array()?;
+        let back_to_back = cur.read_u8()?;
+        let combo = cur.read_array()?;
+        let combo_multiplier = match cur.read_u8()? {
+            0 => false,
+            1 => true,
+            x => bail!("could not unpack bool from byte {x}"),
+        };
+        let perfect_clear = cur.read_u8()?;
+        Ok(AttackTable {
+            clear,
+            tspin,
+            tspin_mini,
+            back_to_back,
+            combo,
+            combo_multiplier,
+            perfect_clear,
+        })
+    }
+}
+
 impl Pack for Game {
+    // `can_hold`, the spin mode, `hold_disabled`, `active_unknown` and
+    // whether the attack table and preview length differ from the default
+    // are packed into a flags byte, the attack table and preview length are
+    // only written if flagged
     fn pack(&self, buf: &mut PackBuffer) {
         self.board.pack(buf);
         self.active.pack(buf);
@@ -157,10 +288,28 @@ impl Pack for Game {
             None => buf.write_u8(255),
         }
         self.queue.pack(buf);
-        match self.can_hold {
-            false => buf.write_u8(0),
-            true => buf.write_u8(1),
+        let custom_table = self.attack_table != AttackTable::default();
+        let spin_mode = match self.spin_mode {
+            SpinMode::TSpin => 0,
+            SpinMode::AllSpin => 1,
         };
+        let custom_preview = self.preview_len as usize != PIECE_QUEUE_MAX_LEN;
+        buf.write_u8(
+            self.can_hold as u8
+                | (custom_table as u8) << 1
+                | spin_mode << 2
+                | (self.hold_disabled as u8) << 3
+                | (custom_preview as u8) << 4
+                | (self.active_unknown as u8) << 5,
+        );
+        buf.write_u8(self.combo);
+        buf.write_u8(self.back_to_back);
+        if custom_table {
+            self.attack_table.pack(buf);
+        }
+        if custom_preview {
+            buf.write_u8(self.preview_len);
+        }
     }
 
     fn unpack(cur: &mut PackCursor) -> Result<Self> {
@@ -171,17 +320,268 @@ impl Pack for Game {
             x => Some(PieceType::from_u8(x)?),
         };
         let queue = PieceQueue::unpack(cur)?;
-        let can_hold = match cur.read_u8()? {
-            0 => false,
-            1 => true,
-            x => bail!("could not unpack bool from byte {x}"),
+        let flags = cur.read_u8()?;
+        if flags >> 6 != 0 {
+            bail!("unknown game flags {flags:#x}");
+        }
+        let spin_mode = match (flags >> 2) & 1 {
+            0 => SpinMode::TSpin,
+            _ => SpinMode::AllSpin,
+        };
+        let combo = cur.read_u8()?;
+        let back_to_back = cur.read_u8()?;
+        let attack_table = match flags & 2 != 0 {
+            true => AttackTable::unpack(cur)?,
+            false => AttackTable::default(),
+        };
+        let preview_len = match flags & 16 != 0 {
+            true => cur.read_u8()?,
+            false => PIECE_QUEUE_MAX_LEN as u8,
         };
+        if preview_len as usize > PIECE_QUEUE_MAX_LEN {
+            bail!("invalid preview length {preview_len}");
+        }
         Ok(Game {
             board,
             active,
             hold,
             queue,
-            can_hold,
+            can_hold: flags & 1 != 0,
+            combo,
+            back_to_back,
+            attack_table,
+            spin_mode,
+            preview_len,
+            hold_disabled: flags & 8 != 0,
+            active_unknown: flags & 32 != 0,
+        })
+    }
+}
+
+impl Pack for Action {
+    fn pack(&self, buf: &mut PackBuffer) {
+        buf.write_u8(self.to_u8());
+    }
+
+    fn unpack(cur: &mut PackCursor) -> Result<Self> {
+        Action::from_u8(cur.read_u8()?)
+    }
+}
+
+impl Pack for SpinKind {
+    fn pack(&self, buf: &mut PackBuffer) {
+        buf.write_u8(match self {
+            SpinKind::None => 0,
+            SpinKind::Mini => 1,
+            SpinKind::Full => 2,
+        });
+    }
+
+    fn unpack(cur: &mut PackCursor) -> Result<Self> {
+        match cur.read_u8()? {
+            0 => Ok(SpinKind::None),
+            1 => Ok(SpinKind::Mini),
+            2 => Ok(SpinKind::Full),
+            x => bail!("unknown spin kind {x}"),
+        }
+    }
+}
+
+impl Pack for LockInfo {
+    // The three bools are packed into a single byte
+    fn pack(&self, buf: &mut PackBuffer) {
+        let flags =
+            self.top_out as u8 | (self.perfect_clear as u8) << 1 | (self.back_to_back as u8) << 2;
+        buf.write_u8(flags);
+        buf.write_u8(self.lines_cleared);
+        self.spin.pack(buf);
+        buf.write_u8(self.combo);
+        buf.write_u8(self.attack);
+    }
+
+    fn unpack(cur: &mut PackCursor) -> Result<Self> {
+        let flags = cur.read_u8()?;
+        if flags >> 3 != 0 {
+            bail!("encountered invalid lock info flags {flags}");
+        }
+        let lines_cleared = cur.read_u8()?;
+        let spin = SpinKind::unpack(cur)?;
+        let combo = cur.read_u8()?;
+        let attack = cur.read_u8()?;
+        Ok(LockInfo {
+            top_out: flags & 1 != 0,
+            lines_cleared,
+            spin,
+            perfect_clear: flags & 2 != 0,
+            combo,
+            back_to_back: flags & 4 != 0,
+            attack,
+        })
+    }
+}
+
+impl Pack for ActionInfo {
+    fn pack(&self, buf: &mut PackBuffer) {
+        match self {
+            ActionInfo::Success => buf.write_u8(0),
+            ActionInfo::Fail => buf.write_u8(1),
+            ActionInfo::Lock(lock_info) => {
+                buf.write_u8(2);
+                lock_info.pack(buf);
+            }
+        }
+    }
+
+    fn unpack(cur: &mut PackCursor) -> Result<Self> {
+        match cur.read_u8()? {
+            0 => Ok(ActionInfo::Success),
+            1 => Ok(ActionInfo::Fail),
+            2 => Ok(ActionInfo::Lock(LockInfo::unpack(cur)?)),
+            x => bail!("unknown action info {x}"),
+        }
+    }
+}
+
+impl Pack for BagKind {
+    fn pack(&self, buf: &mut PackBuffer) {
+        match self {
+            BagKind::Fixed { sequence } => {
+                buf.write_u8(0);
+                sequence.pack(buf);
+            }
+            BagKind::Rng7 { seed } => {
+                buf.write_u8(1);
+                buf.write_u64(*seed);
+            }
+            BagKind::Rng14 { seed } => {
+                buf.write_u8(2);
+                buf.write_u64(*seed);
+            }
+            BagKind::Random { seed } => {
+                buf.write_u8(3);
+                buf.write_u64(*seed);
+            }
+            BagKind::Tgm { seed } => {
+                buf.write_u8(4);
+                buf.write_u64(*seed);
+            }
+            BagKind::Weighted { seed, weights } => {
+                buf.write_u8(5);
+                buf.write_u64(*seed);
+                for weight in weights {
+                    buf.write_u32(*weight);
+                }
+            }
+        }
+    }
+
+    fn unpack(cur: &mut PackCursor) -> Result<Self> {
+        match cur.read_u8()? {
+            0 => Ok(BagKind::Fixed {
+                sequence: Vec::unpack(cur)?,
+            }),
+            1 => Ok(BagKind::Rng7 {
+                seed: cur.read_u64()?,
+            }),
+            2 => Ok(BagKind::Rng14 {
+                seed: cur.read_u64()?,
+            }),
+            3 => Ok(BagKind::Random {
+                seed: cur.read_u64()?,
+            }),
+            4 => Ok(BagKind::Tgm {
+                seed: cur.read_u64()?,
+            }),
+            5 => {
+                let seed = cur.read_u64()?;
+                let mut weights = [0; 7];
+                for weight in weights.iter_mut() {
+                    *weight = cur.read_u32()?;
+                }
+                if weights.iter().all(|&weight| weight == 0) {
+                    bail!("bag weights must not all be zero");
+                }
+                Ok(BagKind::Weighted { seed, weights })
+            }
+            x => bail!("unknown bag kind {x}"),
+        }
+    }
+}
+
+impl Pack for ReplayAction {
+    fn pack(&self, buf: &mut PackBuffer) {
+        buf.write_u64(self.time);
+        self.action.pack(buf);
+        self.info.pack(buf);
+    }
+
+    fn unpack(cur: &mut PackCursor) -> Result<Self> {
+        Ok(ReplayAction {
+            time: cur.read_u64()?,
+            action: Action::unpack(cur)?,
+            info: ActionInfo::unpack(cur)?,
         })
     }
 }
+
+impl Pack for Replay {
+    fn pack(&self, buf: &mut PackBuffer) {
+        self.bag.pack(buf);
+        buf.write_u32(self.bag_offset);
+        self.game.pack(buf);
+        self.actions.pack(buf);
+    }
+
+    fn unpack(cur: &mut PackCursor) -> Result<Self> {
+        Ok(Replay {
+            bag: BagKind::unpack(cur)?,
+            bag_offset: cur.read_u32()?,
+            game: Game::unpack(cur)?,
+            actions: Vec::unpack(cur)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Bag;
+
+    #[test]
+    fn test_pack_game() {
+        let mut bag = Bag::new_rng7(4);
+        let mut game = Game::from_bag(&mut bag);
+        assert_eq!(Game::unpack_base64(&game.pack_base64()).unwrap(), game);
+        // Board header and row count, piece, hold, queue and flags
+        let queue = 1 + (game.queue.len() * 3).div_ceil(8);
+        assert_eq!(game.pack_bytes().len(), 2 + 4 + 1 + queue + 3);
+
+        game.board.add_garbage(3, 4);
+        game.swap_hold();
+        game.refill_queue(&mut bag);
+        game.attack_table = AttackTable::TETRIO;
+        game.spin_mode = SpinMode::AllSpin;
+        game.combo = 3;
+        assert!(game.active.rotate_cw(&game.board));
+        assert_eq!(Game::unpack_base64(&game.pack_base64()).unwrap(), game);
+        game.preview_len = 5;
+        game.hold_disabled = true;
+        assert_eq!(Game::unpack_base64(&game.pack_base64()).unwrap(), game);
+        let mut game = Game::from_bag_with_preview(&mut bag, 0);
+        game.apply(Action::HardDrop);
+        assert!(game.active_unknown);
+        assert_eq!(Game::unpack_base64(&game.pack_base64()).unwrap(), game);
+
+        let mut board = Board::with_size(16, 40, 30).unwrap();
+        board.set_row(0, 0b1011_0000_0000_0001);
+        board.set_row(29, 0b1000_0000_0000_0000);
+        let bytes = board.pack_bytes();
+        assert_eq!(bytes.len(), 4 + 30 * 2);
+        assert_eq!(Board::unpack_bytes(&bytes).unwrap(), board);
+
+        // Non-zero padding bits are rejected
+        let mut bytes = game.queue.pack_bytes();
+        *bytes.last_mut().unwrap() |= 0x80;
+        assert!(PieceQueue::unpack_bytes(&bytes).is_err());
+    }
+}
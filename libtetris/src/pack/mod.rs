@@ -1,59 +1,196 @@
+mod file;
 mod impls;
 
+pub use file::*;
+
 use anyhow::{bail, Result};
 use base64::{engine::general_purpose::URL_SAFE, Engine};
+use file::Crc32;
+use std::{
+    borrow::Cow,
+    io::{self, Read, Write},
+};
+
+/// Number of bytes buffered before they are written to a writer, or read at
+/// once from a reader
+const STREAM_CHUNK: usize = 1 << 16;
 
-/// A buffer of bytes that can be written to
-pub struct PackBuffer {
+/// A buffer of bytes that can be written to, either kept in memory or
+/// streamed to a writer
+pub struct PackBuffer<'a> {
     buf: Vec<u8>,
+    writer: Option<&'a mut dyn Write>,
+    /// Number of bytes flushed to the writer
+    written: u64,
+    /// Checksum of the bytes flushed to the writer
+    crc: Crc32,
+    /// The first error returned by the writer
+    error: Option<io::Error>,
 }
 
-impl PackBuffer {
+impl<'a> PackBuffer<'a> {
     pub fn new() -> Self {
-        PackBuffer { buf: Vec::new() }
+        PackBuffer {
+            buf: Vec::new(),
+            writer: None,
+            written: 0,
+            crc: Crc32::new(),
+            error: None,
+        }
+    }
+
+    /// Create a buffer that streams its bytes to a writer, `finish` must be
+    /// called once everything is written
+    pub fn from_writer(writer: &'a mut dyn Write) -> Self {
+        PackBuffer {
+            writer: Some(writer),
+            ..PackBuffer::new()
+        }
     }
 
+    /// The bytes that haven't been flushed to the writer yet, this is
+    /// everything written if the buffer has no writer
     pub fn read(&self) -> &[u8] {
         &self.buf
     }
 
+    /// Flush the remaining bytes to the writer, returns the total number of
+    /// bytes written and their CRC-32 checksum
+    pub fn finish(mut self) -> Result<(u64, u32)> {
+        self.flush();
+        match self.error {
+            Some(err) => Err(err.into()),
+            None => Ok((self.written, self.crc.finish())),
+        }
+    }
+
+    fn flush(&mut self) {
+        let Some(writer) = self.writer.as_mut() else {
+            return;
+        };
+        if self.error.is_none() {
+            self.error = writer.write_all(&self.buf).err();
+        }
+        self.crc.update(&self.buf);
+        self.written += self.buf.len() as u64;
+        self.buf.clear();
+    }
+
+    fn flush_if_full(&mut self) {
+        if self.writer.is_some() && self.buf.len() >= STREAM_CHUNK {
+            self.flush();
+        }
+    }
+
     pub fn write(&mut self, bytes: &[u8]) {
         self.buf.extend(bytes);
+        self.flush_if_full();
     }
 
     pub fn write_u8(&mut self, val: u8) {
         self.buf.push(val);
+        self.flush_if_full();
     }
 
     pub fn write_u16(&mut self, val: u16) {
-        self.buf.extend(val.to_le_bytes());
+        self.write(&val.to_le_bytes());
     }
 
     pub fn write_u32(&mut self, val: u32) {
-        self.buf.extend(val.to_le_bytes());
+        self.write(&val.to_le_bytes());
     }
 
     pub fn write_u64(&mut self, val: u64) {
-        self.buf.extend(val.to_le_bytes());
+        self.write(&val.to_le_bytes());
     }
 
     pub fn write_packed(&mut self, packed: u64, len: usize) {
-        self.buf.extend(&packed.to_le_bytes()[..len])
+        self.write(&packed.to_le_bytes()[..len])
+    }
+
+    /// Write values of `bits` bits each as a little endian bit stream, the
+    /// last byte is padded with zeros
+    pub fn write_bits(&mut self, values: impl IntoIterator<Item = u64>, bits: usize) {
+        let mut accum: u64 = 0;
+        let mut len = 0;
+        for value in values {
+            for i in 0..bits {
+                accum |= ((value >> i) & 1) << len;
+                len += 1;
+                if len == 8 {
+                    self.buf.push(accum as u8);
+                    accum = 0;
+                    len = 0;
+                }
+            }
+        }
+        if len > 0 {
+            self.buf.push(accum as u8);
+        }
+        self.flush_if_full();
     }
 }
 
-/// A cursor used to read a packed byte stream
+/// A cursor used to read a packed byte stream, either from memory or
+/// streamed from a reader
 pub struct PackCursor<'a> {
     head: usize,
-    bytes: &'a [u8],
+    bytes: Cow<'a, [u8]>,
+    reader: Option<&'a mut dyn Read>,
+    /// Number of bytes left to take from the reader
+    remaining: u64,
+    /// Checksum of the bytes taken from the reader
+    crc: Crc32,
 }
 
 impl<'a> PackCursor<'a> {
     pub fn new(bytes: &'a [u8]) -> Self {
-        PackCursor { head: 0, bytes }
+        PackCursor {
+            head: 0,
+            bytes: Cow::Borrowed(bytes),
+            reader: None,
+            remaining: 0,
+            crc: Crc32::new(),
+        }
+    }
+
+    /// Create a cursor that reads `len` bytes from a reader as they are
+    /// needed
+    pub fn from_reader(reader: &'a mut dyn Read, len: u64) -> Self {
+        PackCursor {
+            head: 0,
+            bytes: Cow::Owned(Vec::new()),
+            reader: Some(reader),
+            remaining: len,
+            crc: Crc32::new(),
+        }
+    }
+
+    /// Take enough bytes from the reader so that `amount` bytes can be read
+    fn fill(&mut self, amount: usize) -> Result<()> {
+        let Some(reader) = self.reader.as_mut() else {
+            return Ok(());
+        };
+        let missing = (self.head + amount - self.bytes.len()) as u64;
+        let take = missing.max(STREAM_CHUNK as u64).min(self.remaining);
+        let bytes = self.bytes.to_mut();
+        bytes.drain(..self.head);
+        self.head = 0;
+        let start = bytes.len();
+        reader.take(take).read_to_end(bytes)?;
+        let read = (bytes.len() - start) as u64;
+        self.crc.update(&bytes[start..]);
+        self.remaining -= read;
+        if read < take {
+            bail!("stream is truncated, {} bytes are missing", self.remaining);
+        }
+        Ok(())
     }
 
     pub fn read(&mut self, amount: usize) -> Result<&[u8]> {
+        if self.head + amount > self.bytes.len() {
+            self.fill(amount)?;
+        }
         if self.head + amount > self.bytes.len() {
             bail!("cursor read past end of bytes");
         }
@@ -96,13 +233,27 @@ impl<'a> PackCursor<'a> {
         Ok(u64::from_le_bytes(buffer))
     }
 
-    pub fn read_all(&mut self) -> &[u8] {
+    /// Read `count` values of `bits` bits each written by `write_bits`
+    pub fn read_bits(&mut self, count: usize, bits: usize) -> Result<Vec<u64>> {
+        let bytes = self.read((count * bits).div_ceil(8))?;
+        let bit = |i: usize| ((bytes[i / 8] >> (i % 8)) & 1) as u64;
+        let values = (0..count)
+            .map(|j| (0..bits).fold(0, |value, i| value | bit(j * bits + i) << i))
+            .collect();
+        let used = (count * bits) % 8;
+        if used != 0 && bytes[bytes.len() - 1] >> used != 0 {
+            bail!("encountered non-zero padding bits");
+        }
+        Ok(values)
+    }
+
+    pub fn read_all(&mut self) -> Result<&[u8]> {
         let amount = self.len();
-        self.read(amount).unwrap()
+        self.read(amount)
     }
 
     pub fn len(&self) -> usize {
-        self.bytes.len() - self.head
+        self.bytes.len() - self.head + self.remaining as usize
     }
 
     pub fn finished(&self) -> bool {
@@ -0,0 +1,439 @@
+use crate::{
+    model::{Bag, Game, PieceQueue},
+    Action, ActionInfo, LockInfo, Piece,
+};
+use serde::{Deserialize, Serialize};
+
+/// Length of a frame in milliseconds, the engine runs at 60 frames per second
+pub const FRAME_MS: f32 = 1000. / 60.;
+
+/// How fast pieces fall on their own
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Gravity {
+    /// Constant gravity, in rows per frame
+    Fixed(f32),
+    /// The guideline gravity curve, the level goes up every 10 lines
+    Guideline,
+}
+
+impl Gravity {
+    /// Rows fallen per frame after a number of lines have been cleared
+    pub fn rows_per_frame(&self, lines: u32) -> f32 {
+        match *self {
+            Gravity::Fixed(rows) => rows,
+            Gravity::Guideline => {
+                let level = (lines / 10).min(19) as f32;
+                let seconds_per_row = (0.8 - level * 0.007).powf(level);
+                1. / (seconds_per_row * 60.)
+            }
+        }
+    }
+}
+
+/// Handling and timing rules used by the realtime engine, times are in frames
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RealtimeConfig {
+    /// Delayed auto shift, frames a shift key is held before it repeats
+    pub das: f32,
+    /// Auto repeat rate, frames between repeated shifts. 0 shifts all the
+    /// way to the wall at once
+    pub arr: f32,
+    /// Soft drop factor, gravity is multiplied by this while soft dropping.
+    /// `None` drops to the bottom at once
+    pub sdf: Option<f32>,
+    pub gravity: Gravity,
+    /// Frames a piece can rest on the stack before it locks
+    pub lock_delay: f32,
+    /// Number of moves on the stack that reset the lock delay, this count is
+    /// reset whenever the piece falls below its lowest row
+    pub move_reset_limit: u32,
+}
+
+impl Default for RealtimeConfig {
+    fn default() -> Self {
+        RealtimeConfig {
+            das: 10.,
+            arr: 1.,
+            sdf: Some(60.),
+            gravity: Gravity::Fixed(1. / 60.),
+            lock_delay: 20.,
+            move_reset_limit: 15,
+        }
+    }
+}
+
+/// A key press or release
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Input {
+    LeftDown,
+    LeftUp,
+    RightDown,
+    RightUp,
+    SoftDropDown,
+    SoftDropUp,
+    HardDrop,
+    RotateCw,
+    RotateCcw,
+    Rotate180,
+    Hold,
+}
+
+/// A piece locked by the realtime engine
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LockEvent {
+    /// The piece right before it was locked
+    pub piece: Piece,
+    pub lock_info: LockInfo,
+    /// Frame on which the piece was locked
+    pub frame: u64,
+}
+
+/// Wraps a game with handling, gravity and lock delay, and advances it in
+/// time by frames or milliseconds
+#[derive(Debug, Clone)]
+pub struct Realtime {
+    pub config: RealtimeConfig,
+    game: Game,
+    frame: u64,
+    /// Milliseconds that haven't made up a full frame yet
+    remainder: f32,
+    pieces: u32,
+    lines: u32,
+    top_out: bool,
+    left: bool,
+    right: bool,
+    /// Direction of the shift key currently being auto repeated
+    shift: i8,
+    das_timer: f32,
+    arr_timer: f32,
+    soft_drop: bool,
+    /// Fraction of a row fallen due to gravity
+    fall: f32,
+    lock_timer: f32,
+    move_resets: u32,
+    lowest_y: i8,
+    locks: Vec<LockEvent>,
+}
+
+impl Realtime {
+    pub fn new(game: Game, config: RealtimeConfig) -> Self {
+        Realtime {
+            config,
+            game,
+            frame: 0,
+            remainder: 0.,
+            pieces: 0,
+            lines: 0,
+            top_out: false,
+            left: false,
+            right: false,
+            shift: 0,
+            das_timer: 0.,
+            arr_timer: 0.,
+            soft_drop: false,
+            fall: 0.,
+            lock_timer: 0.,
+            move_resets: 0,
+            lowest_y: game.active.position_y,
+            locks: Vec::new(),
+        }
+    }
+
+    pub fn game(&self) -> &Game {
+        &self.game
+    }
+
+    /// Number of frames elapsed
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    pub fn pieces(&self) -> u32 {
+        self.pieces
+    }
+
+    pub fn lines(&self) -> u32 {
+        self.lines
+    }
+
+    pub fn top_out(&self) -> bool {
+        self.top_out
+    }
+
+    /// Pieces per second over the elapsed game time
+    pub fn pps(&self) -> f32 {
+        self.pieces as f32 * 60. / self.frame.max(1) as f32
+    }
+
+    pub fn refill_queue(&mut self, bag: &mut Bag) {
+        let dealt = self.game.active_unknown;
+        self.game.refill_queue(bag);
+        // Without a preview the next piece only spawns once it is dealt
+        if dealt {
+            self.reset_piece();
+            self.top_out = self.game.board.intersects_with(&self.game.active);
+        }
+    }
+
+    /// Replace the queue, for when pieces are dealt by something other than
+    /// a bag
+    pub fn set_queue(&mut self, queue: PieceQueue) {
+        self.game.queue = queue;
+    }
+
+    /// Replace the game, such as when undoing a piece. Held keys are kept,
+    /// and the active piece starts falling from its current position.
+    pub fn set_game(&mut self, game: Game) {
+        self.game = game;
+        self.top_out = game.board.intersects_with(&game.active);
+        self.reset_piece();
+    }
+
+    /// Take the pieces locked since the last call
+    pub fn drain_locks(&mut self) -> Vec<LockEvent> {
+        std::mem::take(&mut self.locks)
+    }
+
+    /// Apply a key press or release, returns whether it changed the game
+    pub fn input(&mut self, input: Input) -> bool {
+        if self.top_out {
+            return false;
+        }
+        match input {
+            Input::LeftDown => {
+                self.left = true;
+                self.start_shift(-1)
+            }
+            Input::RightDown => {
+                self.right = true;
+                self.start_shift(1)
+            }
+            Input::LeftUp => {
+                self.left = false;
+                if self.shift == -1 {
+                    self.shift = if self.right { 1 } else { 0 };
+                    self.das_timer = self.config.das;
+                }
+                false
+            }
+            Input::RightUp => {
+                self.right = false;
+                if self.shift == 1 {
+                    self.shift = if self.left { -1 } else { 0 };
+                    self.das_timer = self.config.das;
+                }
+                false
+            }
+            Input::SoftDropDown => {
+                self.soft_drop = true;
+                false
+            }
+            Input::SoftDropUp => {
+                self.soft_drop = false;
+                false
+            }
+            Input::HardDrop => {
+                self.game.active.soft_drop(&self.game.board);
+                self.lock()
+            }
+            Input::RotateCw => self.apply_move(Action::RotateCw),
+            Input::RotateCcw => self.apply_move(Action::RotateCcw),
+            Input::Rotate180 => self.apply_move(Action::Rotate180),
+            Input::Hold => {
+                if self.game.swap_hold() {
+                    self.reset_piece();
+                    true
+                } else {
+                    false
+                }
+            }
+        }
+    }
+
+    pub fn advance_frames(&mut self, frames: u32) {
+        for _ in 0..frames {
+            self.tick();
+        }
+    }
+
+    /// Advance by a number of milliseconds, partial frames are carried over
+    /// to the next call
+    pub fn advance_ms(&mut self, ms: f32) {
+        self.remainder += ms;
+        while self.remainder >= FRAME_MS {
+            self.remainder -= FRAME_MS;
+            self.tick();
+        }
+    }
+
+    fn tick(&mut self) {
+        if self.top_out {
+            return;
+        }
+        self.frame += 1;
+
+        // Auto shift
+        if self.shift != 0 {
+            if self.das_timer > 0. {
+                self.das_timer -= 1.;
+            }
+            if self.das_timer <= 0. {
+                let action = if self.shift < 0 {
+                    Action::ShiftLeft
+                } else {
+                    Action::ShiftRight
+                };
+                if self.config.arr <= 0. {
+                    while self.apply_move(action) {}
+                } else {
+                    self.arr_timer += 1.;
+                    while self.arr_timer >= self.config.arr {
+                        self.arr_timer -= self.config.arr;
+                        if !self.apply_move(action) {
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+
+        // Gravity
+        let mut rows = self.config.gravity.rows_per_frame(self.lines);
+        if self.soft_drop {
+            rows = match self.config.sdf {
+                Some(sdf) => rows * sdf,
+                None => f32::INFINITY,
+            };
+        }
+        self.fall += rows;
+        while self.fall >= 1. {
+            self.fall -= 1.;
+            if !self.game.active.shift_down(&self.game.board) {
+                self.fall = 0.;
+                break;
+            }
+            self.on_fall();
+        }
+
+        // Lock delay
+        let mut piece = self.game.active;
+        if !piece.shift_down(&self.game.board) {
+            self.lock_timer += 1.;
+            if self.lock_timer >= self.config.lock_delay {
+                self.lock();
+            }
+        }
+    }
+
+    fn start_shift(&mut self, shift: i8) -> bool {
+        self.shift = shift;
+        self.das_timer = self.config.das;
+        self.arr_timer = 0.;
+        let action = if shift < 0 {
+            Action::ShiftLeft
+        } else {
+            Action::ShiftRight
+        };
+        self.apply_move(action)
+    }
+
+    /// Apply a shift or rotation, a successful move on the stack resets the
+    /// lock delay
+    fn apply_move(&mut self, action: Action) -> bool {
+        if self.game.apply(action) == ActionInfo::Fail {
+            return false;
+        }
+        if self.lock_timer > 0. && self.move_resets < self.config.move_reset_limit {
+            self.lock_timer = 0.;
+            self.move_resets += 1;
+        }
+        self.on_fall();
+        true
+    }
+
+    fn on_fall(&mut self) {
+        if self.game.active.position_y < self.lowest_y {
+            self.lowest_y = self.game.active.position_y;
+            self.lock_timer = 0.;
+            self.move_resets = 0;
+        }
+    }
+
+    fn lock(&mut self) -> bool {
+        let piece = self.game.active;
+        let ActionInfo::Lock(lock_info) = self.game.lock() else {
+            return false;
+        };
+        self.locks.push(LockEvent {
+            piece,
+            lock_info,
+            frame: self.frame,
+        });
+        self.pieces += 1;
+        self.lines += lock_info.lines_cleared as u32;
+        self.reset_piece();
+        let blocked =
+            !self.game.active_unknown && self.game.board.intersects_with(&self.game.active);
+        if lock_info.top_out || blocked {
+            self.top_out = true;
+        }
+        true
+    }
+
+    fn reset_piece(&mut self) {
+        self.fall = 0.;
+        self.lock_timer = 0.;
+        self.move_resets = 0;
+        self.lowest_y = self.game.active.position_y;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_realtime() {
+        let mut bag = Bag::new_rng7(0);
+        let game = Game::from_bag(&mut bag);
+        let config = RealtimeConfig {
+            das: 5.,
+            arr: 0.,
+            gravity: Gravity::Fixed(0.5),
+            ..Default::default()
+        };
+        let mut realtime = Realtime::new(game, config);
+        let spawn = game.active;
+
+        // Gravity
+        realtime.advance_frames(4);
+        assert_eq!(realtime.game().active.position_y, spawn.position_y - 2);
+        realtime.advance_ms(FRAME_MS * 2.5);
+        assert_eq!(realtime.frame(), 6);
+
+        // Das and arr
+        assert!(realtime.input(Input::LeftDown));
+        assert_eq!(realtime.game().active.position_x, spawn.position_x - 1);
+        realtime.advance_frames(5);
+        let mut wall = realtime.game().active;
+        assert!(!wall.shift_left(&realtime.game().board));
+        realtime.input(Input::LeftUp);
+
+        // Lock delay
+        realtime.input(Input::SoftDropDown);
+        realtime.advance_frames(10);
+        assert!(realtime.drain_locks().is_empty());
+        realtime.advance_frames(config.lock_delay as u32);
+        let locks = realtime.drain_locks();
+        assert_eq!(locks.len(), 1);
+        assert_eq!(locks[0].piece.position_x, wall.position_x);
+
+        realtime.refill_queue(&mut bag);
+        assert!(realtime.input(Input::HardDrop));
+        assert_eq!(realtime.pieces(), 2);
+        assert!(realtime.pps() > 0.);
+    }
+}
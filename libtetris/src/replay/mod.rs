@@ -0,0 +1,201 @@
+use crate::{
+    model::{Bag, BagKind, Game},
+    Action, ActionInfo,
+};
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+/// An action applied during a recorded game
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReplayAction {
+    /// Milliseconds since the start of the game
+    pub time: u64,
+    pub action: Action,
+    /// The result of the action when it was recorded
+    pub info: ActionInfo,
+}
+
+/// A recorded game that can be played back exactly. The queue is refilled
+/// from the bag after every action.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Replay {
+    pub bag: BagKind,
+    /// Number of pieces drawn from the bag before the first action
+    pub bag_offset: u32,
+    /// The game before the first action
+    pub game: Game,
+    pub actions: Vec<ReplayAction>,
+}
+
+impl Replay {
+    /// Start recording a new game dealt from a bag
+    pub fn new(bag: BagKind) -> Self {
+        let game = Game::from_bag(&mut bag.bag());
+        let bag_offset = game.queue.len() as u32 + 1;
+        Replay::from_game(bag, bag_offset, game)
+    }
+
+    /// Start recording from an existing game, `bag_offset` pieces have
+    /// already been drawn from the bag
+    pub fn from_game(bag: BagKind, bag_offset: u32, game: Game) -> Self {
+        Replay {
+            bag,
+            bag_offset,
+            game,
+            actions: Vec::new(),
+        }
+    }
+
+    /// Create a player positioned at the start of the replay
+    pub fn player(&self) -> ReplayPlayer<'_> {
+        let mut bag = self.bag.bag();
+        for _ in 0..self.bag_offset {
+            bag.next();
+        }
+        ReplayPlayer {
+            replay: self,
+            bag,
+            game: self.game,
+            index: 0,
+        }
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    pub fn from_json(text: &str) -> Result<Self> {
+        Ok(serde_json::from_str(text)?)
+    }
+
+    /// Play back every action, returns the final game or an error if any
+    /// action doesn't match its recorded result
+    pub fn verify(&self) -> Result<Game> {
+        let mut player = self.player();
+        while player.step()?.is_some() {}
+        Ok(*player.game())
+    }
+}
+
+/// Plays back a replay one action at a time
+#[derive(Debug, Clone)]
+pub struct ReplayPlayer<'a> {
+    replay: &'a Replay,
+    bag: Bag,
+    game: Game,
+    index: usize,
+}
+
+impl ReplayPlayer<'_> {
+    /// The game after the actions played so far
+    pub fn game(&self) -> &Game {
+        &self.game
+    }
+
+    /// Number of actions played so far
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Apply the next action, returns `None` at the end of the replay
+    pub fn step(&mut self) -> Result<Option<ReplayAction>> {
+        let Some(&action) = self.replay.actions.get(self.index) else {
+            return Ok(None);
+        };
+        let info = self.game.apply(action.action);
+        if info != action.info {
+            bail!(
+                "action {} ({}) at {}ms: expected {:?}, got {info:?}",
+                self.index,
+                action.action,
+                action.time,
+                action.info
+            );
+        }
+        self.game.refill_queue(&mut self.bag);
+        self.index += 1;
+        Ok(Some(action))
+    }
+}
+
+/// Records a game as it is played
+#[derive(Debug, Clone)]
+pub struct ReplayRecorder {
+    replay: Replay,
+    bag: Bag,
+    game: Game,
+}
+
+impl ReplayRecorder {
+    pub fn new(bag: BagKind) -> Self {
+        let replay = Replay::new(bag);
+        let bag = replay.player().bag;
+        ReplayRecorder {
+            game: replay.game,
+            replay,
+            bag,
+        }
+    }
+
+    pub fn game(&self) -> &Game {
+        &self.game
+    }
+
+    /// Apply an action to the game and record it
+    pub fn apply(&mut self, time: u64, action: Action) -> ActionInfo {
+        let info = self.game.apply(action);
+        self.game.refill_queue(&mut self.bag);
+        self.replay
+            .actions
+            .push(ReplayAction { time, action, info });
+        info
+    }
+
+    pub fn replay(&self) -> &Replay {
+        &self.replay
+    }
+
+    pub fn finish(self) -> Replay {
+        self.replay
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Ai, Evaluation, Pack, SimpleAi};
+
+    #[test]
+    fn test_replay() {
+        let mut recorder = ReplayRecorder::new(BagKind::Rng7 { seed: 5 });
+        let mut ai = SimpleAi::new();
+        let mut time = 0;
+        for _ in 0..20 {
+            let Evaluation::Success { actions, .. } = ai.evaluate(recorder.game()) else {
+                panic!("evaluation failed");
+            };
+            for action in actions {
+                time += 50;
+                recorder.apply(time, action);
+            }
+        }
+        let game = *recorder.game();
+        let replay = recorder.finish();
+        assert_eq!(replay.verify().unwrap(), game);
+
+        let unpacked = Replay::unpack_base64(&replay.pack_base64()).unwrap();
+        assert_eq!(unpacked, replay);
+        let json = replay.to_json().unwrap();
+        assert_eq!(Replay::from_json(&json).unwrap(), replay);
+
+        // Tampered replays fail to verify
+        let mut tampered = replay.clone();
+        tampered.actions[0].info = match tampered.actions[0].info {
+            ActionInfo::Fail => ActionInfo::Success,
+            _ => ActionInfo::Fail,
+        };
+        assert!(tampered.verify().is_err());
+    }
+}
@@ -1,4 +1,7 @@
-use crate::{Action, Board, Evaluation, PieceQueue, PieceType, BOARD_HEIGHT, BOARD_WIDTH};
+use crate::{
+    Action, Board, Evaluation, PieceQueue, PieceType, SearchStats, BOARD_HEIGHT,
+    BOARD_VISIBLE_HEIGHT, BOARD_WIDTH,
+};
 use anyhow::{bail, Result};
 use serde::{Deserialize, Serialize};
 
@@ -27,28 +30,49 @@ impl From<PieceQueue> for SerializedPieceQueue {
     }
 }
 
-/// Serialized version of game board
+/// Serialized version of game board, `' '` is an empty tile and any other
+/// character is a filled tile
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub(crate) struct SerializedBoard {
     matrix: Vec<char>,
+    #[serde(default = "default_width")]
+    width: usize,
+    #[serde(default = "default_height")]
+    height: usize,
+    #[serde(default = "default_visible_height")]
+    visible_height: usize,
+}
+
+fn default_width() -> usize {
+    BOARD_WIDTH
+}
+
+fn default_height() -> usize {
+    BOARD_HEIGHT
+}
+
+fn default_visible_height() -> usize {
+    BOARD_VISIBLE_HEIGHT
 }
 
 impl TryFrom<SerializedBoard> for Board {
     type Error = anyhow::Error;
 
     fn try_from(ser: SerializedBoard) -> Result<Self> {
-        if ser.matrix.len() < BOARD_WIDTH * BOARD_HEIGHT {
+        let mut board = Board::with_size(ser.width, ser.height, ser.visible_height)?;
+        let (width, height) = (board.width(), board.height());
+        if ser.matrix.len() < width * height {
             bail!(
                 "expected len >={}, got {}",
-                BOARD_WIDTH * BOARD_HEIGHT,
+                width * height,
                 ser.matrix.len()
             );
         }
 
-        let mut board = Board::new();
-        for i in 0..BOARD_WIDTH {
-            for j in 0..BOARD_HEIGHT {
-                let cell = ser.matrix[j * BOARD_WIDTH + i];
+        for i in 0..width {
+            for j in 0..height {
+                let cell = ser.matrix[j * width + i];
                 let val = cell != ' ';
                 board.set(i, j, val);
             }
@@ -60,12 +84,17 @@ impl TryFrom<SerializedBoard> for Board {
 impl From<Board> for SerializedBoard {
     fn from(board: Board) -> Self {
         let mut matrix = Vec::new();
-        for j in 0..BOARD_HEIGHT {
-            for i in 0..BOARD_WIDTH {
-                matrix.push(if board.get(i, j) { ' ' } else { 'G' });
+        for j in 0..board.height() {
+            for i in 0..board.width() {
+                matrix.push(if board.get(i, j) { 'G' } else { ' ' });
             }
         }
-        SerializedBoard { matrix }
+        SerializedBoard {
+            matrix,
+            width: board.width(),
+            height: board.height(),
+            visible_height: board.visible_height(),
+        }
     }
 }
 
@@ -77,6 +106,8 @@ pub enum SerializedEvaluation {
         success: bool,
         actions: Vec<Action>,
         score: f32,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        stats: Option<SearchStats>,
     },
     Fail {
         success: bool,
@@ -87,10 +118,15 @@ pub enum SerializedEvaluation {
 impl From<Evaluation> for SerializedEvaluation {
     fn from(ai_res: Evaluation) -> Self {
         match ai_res {
-            Evaluation::Success { actions, score } => SerializedEvaluation::Success {
+            Evaluation::Success {
+                actions,
+                score,
+                stats,
+            } => SerializedEvaluation::Success {
                 success: true,
                 actions,
                 score,
+                stats,
             },
             Evaluation::Fail { message } => SerializedEvaluation::Fail {
                 success: false,
@@ -103,9 +139,16 @@ impl From<Evaluation> for SerializedEvaluation {
 impl From<SerializedEvaluation> for Evaluation {
     fn from(value: SerializedEvaluation) -> Self {
         match value {
-            SerializedEvaluation::Success { actions, score, .. } => {
-                Evaluation::Success { actions, score }
-            }
+            SerializedEvaluation::Success {
+                actions,
+                score,
+                stats,
+                ..
+            } => Evaluation::Success {
+                actions,
+                score,
+                stats,
+            },
             SerializedEvaluation::Fail { message, .. } => Evaluation::Fail { message },
         }
     }
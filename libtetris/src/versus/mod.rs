@@ -0,0 +1,155 @@
+use crate::{
+    ai::play_piece,
+    model::{Bag, Game, GarbageQueue},
+    Ai,
+};
+use std::time::Duration;
+
+/// Per-player statistics collected over a versus match
+#[derive(Debug, Clone, Default)]
+pub struct VersusStats {
+    /// Number of pieces locked
+    pub pieces: u32,
+    /// Number of lines cleared
+    pub lines_cleared: u32,
+    /// Total attack produced, before cancelling
+    pub attack: u32,
+    /// Number of garbage lines sent to the opponent, after cancelling
+    pub lines_sent: u32,
+    /// Total time spent in `Ai::evaluate`
+    pub think_time: Duration,
+}
+
+impl VersusStats {
+    /// Pieces per second, measured against the time spent thinking
+    pub fn pps(&self) -> f32 {
+        self.pieces as f32 / self.think_time.as_secs_f32().max(f32::EPSILON)
+    }
+
+    /// Attack per minute, measured against the time spent thinking
+    pub fn apm(&self) -> f32 {
+        self.attack as f32 * 60. / self.think_time.as_secs_f32().max(f32::EPSILON)
+    }
+}
+
+/// The result of a versus match
+#[derive(Debug, Clone)]
+pub struct VersusResult {
+    /// Index of the winning player, `None` if the match was a draw
+    pub winner: Option<usize>,
+    pub stats: [VersusStats; 2],
+}
+
+impl VersusResult {
+    /// The same result with the two players swapped
+    pub fn swapped(mut self) -> Self {
+        self.winner = self.winner.map(|winner| 1 - winner);
+        self.stats.swap(0, 1);
+        self
+    }
+}
+
+/// A two player match, each player has their own game and garbage queue.
+/// Both games are dealt the same piece sequence.
+#[derive(Debug, Clone)]
+pub struct Versus {
+    pub seed: u64,
+    /// The match is a draw if neither player has topped out after this many
+    /// pieces each
+    pub max_pieces: u32,
+    /// Number of locks before received garbage is able to rise
+    pub garbage_delay: u8,
+    /// Probability that the garbage hole changes column between rows
+    pub garbage_messiness: f32,
+}
+
+impl Versus {
+    pub fn new(seed: u64) -> Self {
+        Versus {
+            seed,
+            max_pieces: 500,
+            garbage_delay: 1,
+            garbage_messiness: 0.,
+        }
+    }
+
+    /// Play a match between two Ais, players alternate placing one piece
+    /// at a time. A player loses when they top out or fail to evaluate.
+    /// `player1` moves first, which decides the winner if both would top out
+    /// on the same turn, so seats should be alternated between matches.
+    pub fn run(&self, player1: &mut dyn Ai, player2: &mut dyn Ai) -> VersusResult {
+        let mut bags = [Bag::new_rng7(self.seed), Bag::new_rng7(self.seed)];
+        let mut games = [Game::from_bag(&mut bags[0]), Game::from_bag(&mut bags[1])];
+        let mut garbage = [
+            GarbageQueue::new(self.seed, &games[0].board),
+            GarbageQueue::new(self.seed.wrapping_add(1), &games[1].board),
+        ];
+        for queue in garbage.iter_mut() {
+            queue.delay = self.garbage_delay;
+            queue.messiness = self.garbage_messiness;
+        }
+        let mut stats = [VersusStats::default(), VersusStats::default()];
+
+        for _ in 0..self.max_pieces {
+            for i in 0..2 {
+                let ai: &mut dyn Ai = if i == 0 { player1 } else { player2 };
+                let game = &mut games[i];
+                let Some((lock_info, elapsed)) = play_piece(ai, game, &mut bags[i]) else {
+                    return VersusResult {
+                        winner: Some(1 - i),
+                        stats,
+                    };
+                };
+
+                stats[i].think_time += elapsed;
+                stats[i].pieces += 1;
+                stats[i].lines_cleared += lock_info.lines_cleared as u32;
+                stats[i].attack += lock_info.attack as u32;
+                let outgoing = garbage[i].on_lock(game, &lock_info);
+                stats[i].lines_sent += outgoing as u32;
+                garbage[1 - i].receive(outgoing);
+
+                let topped_out = lock_info.top_out
+                    || game.board.topped_out()
+                    || game.board.intersects_with(&game.active);
+                if topped_out {
+                    return VersusResult {
+                        winner: Some(1 - i),
+                        stats,
+                    };
+                }
+            }
+        }
+
+        VersusResult {
+            winner: None,
+            stats,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::SimpleAi;
+
+    #[test]
+    fn test_versus() {
+        let mut versus = Versus::new(0);
+        versus.max_pieces = 20;
+        let result = versus.run(&mut SimpleAi::new(), &mut SimpleAi::new());
+        for stats in result.stats.iter() {
+            assert!(stats.pieces > 0 && stats.pieces <= 20);
+            assert!(stats.lines_sent <= stats.attack);
+        }
+        if result.winner.is_none() {
+            assert_eq!(result.stats[0].pieces, 20);
+            assert_eq!(result.stats[1].pieces, 20);
+        }
+
+        let pieces = [result.stats[0].pieces, result.stats[1].pieces];
+        let swapped = result.clone().swapped();
+        assert_eq!(swapped.winner, result.winner.map(|winner| 1 - winner));
+        assert_eq!([swapped.stats[1].pieces, swapped.stats[0].pieces], pieces);
+    }
+}
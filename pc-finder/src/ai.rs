@@ -1,5 +1,5 @@
 use crate::{PcBoard, PcTable};
-use anyhow::Result;
+use anyhow::{bail, Result};
 use libtetris::*;
 use std::collections::VecDeque;
 
@@ -13,6 +13,10 @@ struct PcGame {
 
 impl PcGame {
     pub fn from_game(game: Game) -> Result<Self> {
+        // The pc table's moves are generated with SRS kicks
+        if game.active.rotation_system != RotationSystem::Srs {
+            bail!("pc table only supports SRS");
+        }
         let board = PcBoard::try_from(game.board)?;
         Ok(PcGame {
             board,
@@ -86,13 +90,22 @@ impl<'a> PcChild<'a> {
 pub struct PcFinderAi {
     table: PcTable,
     simple_ai: SimpleAi,
+    /// The kind of bag the game is dealt from, used to guess pieces past
+    /// the end of the queue
+    bag: BagKind,
 }
 
 impl PcFinderAi {
     pub fn new(pc_table: PcTable) -> Self {
+        PcFinderAi::with_bag(pc_table, BagKind::default())
+    }
+
+    /// Only the kind of the bag is used, not its seed
+    pub fn with_bag(pc_table: PcTable, bag: BagKind) -> Self {
         PcFinderAi {
             table: pc_table,
             simple_ai: SimpleAi::new(),
+            bag,
         }
     }
 }
@@ -104,13 +117,22 @@ impl Ai for PcFinderAi {
             Err(_) => return self.simple_ai.evaluate(game),
         };
 
-        // Trick: slightly extend the queue with likely pieces to get more
-        // accurate results
-        for piece_type in PieceType::ALL {
-            let found = pc_game.queue.iter().find(|&p| p == piece_type).is_some();
-            if !found && pc_game.queue.len() != PIECE_QUEUE_MAX_LEN {
-                pc_game.queue.enqueue(piece_type);
-            }
+        // Extend the queue with pieces that are more likely than chance,
+        // such as the rest of the current 7-bag, to get more accurate results
+        let recent = std::iter::once(pc_game.current)
+            .chain(pc_game.queue.iter())
+            .collect::<Vec<_>>();
+        let room = (PIECE_QUEUE_MAX_LEN - pc_game.queue.len()).min(7);
+        let mut extended = Vec::new();
+        for slot in self.bag.next_pieces(&recent, room) {
+            let likely = slot.possible().into_iter().find(|&piece_type| {
+                !extended.contains(&piece_type) && slot.probability(piece_type) > 1. / 7. + 1e-3
+            });
+            let Some(piece_type) = likely else {
+                break;
+            };
+            extended.push(piece_type);
+            pc_game.queue.enqueue(piece_type);
         }
 
         let children = pc_game.children(&self.table).collect::<Vec<_>>();
@@ -119,6 +141,7 @@ impl Ai for PcFinderAi {
                 return Evaluation::Success {
                     actions: child.actions(),
                     score: 0.0,
+                    stats: None,
                 };
             }
         }
@@ -153,6 +176,7 @@ impl Ai for PcFinderAi {
                     return Evaluation::Success {
                         actions: children[frame.ancestor].actions(),
                         score: frame.depth as f32,
+                        stats: None,
                     };
                 }
                 queue.push_back(Frame {
@@ -171,6 +195,7 @@ impl Ai for PcFinderAi {
             Some(best_child) => Evaluation::Success {
                 actions: best_child.actions(),
                 score: 10.0,
+                stats: None,
             },
             None => self.simple_ai.evaluate(game),
         }
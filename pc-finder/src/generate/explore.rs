@@ -1,12 +1,15 @@
 use crate::{PcBoard, Tess};
 use anyhow::{bail, Result};
-use libtetris::{Board, Fin, Game, Pack, Piece, PieceType, BOARD_WIDTH};
+use libtetris::{Board, Fin, Game, PackFile, Piece, PieceType, BOARD_WIDTH};
 use std::{
     collections::{HashMap, HashSet, VecDeque},
     fs::File,
-    io::{Read, Write},
+    io::BufReader,
 };
 
+const EDGES_FILE: PackFile = PackFile::new(*b"EDGE", 1);
+const TESS_STATS_FILE: PackFile = PackFile::new(*b"TSTA", 1);
+
 /// Check whether the pieces on a board fit a given tesselation
 fn board_fits_tess(board: PcBoard, tess: Tess) -> bool {
     #[inline]
@@ -150,35 +153,24 @@ pub fn explore_graph(tessellations: Vec<Tess>) -> Result<Vec<(PcBoard, PcBoard)>
     let output = explore_bfs(tessellations, &mut tess_stats);
 
     println!("Saving tessellation stats to data/edges-tess-stats.bin");
-    let bytes = tess_stats.pack_bytes();
-    let mut file = File::create("data/edges-tess-stats.bin")?;
-    file.write_all(&bytes)?;
+    TESS_STATS_FILE.save(&tess_stats, "data/edges-tess-stats.bin")?;
 
     println!("Saving graph edges to data/edges.bin");
-    let bytes = output.pack_bytes();
-    let mut file = File::create("data/edges.bin")?;
-    file.write_all(&bytes)?;
+    EDGES_FILE.save(&output, "data/edges.bin")?;
 
     Ok(output)
 }
 
 pub fn read_edges() -> Result<Vec<(PcBoard, PcBoard)>> {
     println!("Reading graph edges from data/edges.bin");
-    let mut file = File::open("data/edges.bin")?;
-    let mut data = Vec::new();
-    file.read_to_end(&mut data)?;
-    Vec::<(PcBoard, PcBoard)>::unpack_bytes(&data)
+    EDGES_FILE.open("data/edges.bin")
 }
 
 pub fn read_tess_stats() -> Result<HashMap<Tess, u64>> {
     println!("Reading tessellation stats");
-    let file = File::open("data/edges-tess-stats.bin");
-    if let Ok(mut file) = file {
+    if let Ok(file) = File::open("data/edges-tess-stats.bin") {
         println!("Reading tessellation states from data/edges-tess-stats.bin");
-        let mut data = Vec::new();
-        file.read_to_end(&mut data)?;
-        let output = HashMap::<Tess, u64>::unpack_bytes(&data)?;
-        return Ok(output);
+        return TESS_STATS_FILE.read(&mut BufReader::new(file));
     }
     bail!("Could not open file data/edges-tess-stats.bin");
 }
@@ -1,13 +1,11 @@
 use crate::PcBoard;
 use anyhow::Result;
-use libtetris::Pack;
-use std::{
-    collections::{HashMap, HashSet, VecDeque},
-    fs::File,
-    io::{Read, Write},
-};
+use libtetris::PackFile;
+use std::collections::{HashMap, HashSet, VecDeque};
 use tinyvec::TinyVec;
 
+const PRUNED_FILE: PackFile = PackFile::new(*b"PRUN", 1);
+
 fn prune_bfs(edges: Vec<(PcBoard, PcBoard)>) -> Vec<(PcBoard, PcBoard)> {
     let mut backlinks = HashMap::<PcBoard, TinyVec<[PcBoard; 4]>>::new();
     for &(parent, child) in edges.iter() {
@@ -59,17 +57,12 @@ pub fn prune_graph(edges: Vec<(PcBoard, PcBoard)>) -> Result<Vec<(PcBoard, PcBoa
     let output = prune_bfs(edges);
 
     println!("Saving pruned edges to data/pruned.bin");
-    let bytes = output.pack_bytes();
-    let mut file = File::create("data/pruned.bin")?;
-    file.write_all(&bytes)?;
+    PRUNED_FILE.save(&output, "data/pruned.bin")?;
 
     Ok(output)
 }
 
 pub fn read_pruned() -> Result<Vec<(PcBoard, PcBoard)>> {
     println!("Reading graph edges from data/pruned.bin");
-    let mut file = File::open("data/pruned.bin")?;
-    let mut data = Vec::new();
-    file.read_to_end(&mut data)?;
-    Vec::<(PcBoard, PcBoard)>::unpack_bytes(&data)
+    PRUNED_FILE.open("data/pruned.bin")
 }
@@ -1,11 +1,7 @@
-use crate::{PcBoard, PcTable, PcTableChild};
+use crate::{PcBoard, PcTable, PcTableChild, PC_TABLE_FILE};
 use anyhow::Result;
-use libtetris::{Board, Fin, Game, Pack, Piece, PieceType};
-use std::{
-    collections::HashSet,
-    fs::File,
-    io::{Read, Write},
-};
+use libtetris::{Board, Fin, Game, Piece, PieceType};
+use std::collections::HashSet;
 use tinyvec::TinyVec;
 
 fn construct_table(pruned: Vec<(PcBoard, PcBoard)>) -> PcTable {
@@ -63,17 +59,12 @@ pub fn generate_pc_table(pruned: Vec<(PcBoard, PcBoard)>) -> Result<PcTable> {
     let output = construct_table(pruned);
 
     println!("Saving PcTable to data/pc-table.bin");
-    let bytes = output.pack_bytes();
-    let mut file = File::create("data/pc-table.bin")?;
-    file.write_all(&bytes)?;
+    PC_TABLE_FILE.save(&output, "data/pc-table.bin")?;
 
     Ok(output)
 }
 
 pub fn read_pc_table() -> Result<PcTable> {
     println!("Reading PcTable from data/pc-table.bin");
-    let mut file = File::open("data/pc-table.bin")?;
-    let mut data = Vec::new();
-    file.read_to_end(&mut data)?;
-    PcTable::unpack_bytes(&data)
+    PC_TABLE_FILE.open("data/pc-table.bin")
 }
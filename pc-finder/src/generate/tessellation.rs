@@ -1,11 +1,9 @@
 use crate::{NormPiece, PcBoard, Tess};
 use anyhow::Result;
-use libtetris::{Pack, Piece, PieceInfo, PieceType};
-use std::{
-    collections::{HashSet, VecDeque},
-    fs::File,
-    io::{Read, Write},
-};
+use libtetris::{PackFile, Piece, PieceInfo, PieceType};
+use std::collections::{HashSet, VecDeque};
+
+const TESS_FILE: PackFile = PackFile::new(*b"TESS", 1);
 
 // Generate all possible permutations of normalized pieces
 fn generate_all_norm_pieces() -> Vec<NormPiece> {
@@ -146,17 +144,12 @@ pub fn generate_tessellations() -> Result<Vec<Tess>> {
 
     // Save to file
     println!("Saving tessellations to data/tessellations.bin");
-    let bytes = output.pack_bytes();
-    let mut file = File::create("data/tessellations.bin")?;
-    file.write_all(&bytes)?;
+    TESS_FILE.save(&output, "data/tessellations.bin")?;
 
     Ok(output)
 }
 
 pub fn read_tessellations() -> Result<Vec<Tess>> {
     println!("Reading tessellations from data/tessellations.bin");
-    let mut file = File::open("data/tessellations.bin")?;
-    let mut data = Vec::new();
-    file.read_to_end(&mut data)?;
-    Vec::<Tess>::unpack_bytes(&data)
+    TESS_FILE.open("data/tessellations.bin")
 }
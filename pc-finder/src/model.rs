@@ -55,8 +55,11 @@ impl PcBoard {
 impl TryFrom<Board> for PcBoard {
     type Error = Error;
 
-    /// Fails if the height of the board is greater than 4
+    /// Fails if the board is not 10 wide or its height is greater than 4
     fn try_from(value: Board) -> Result<Self> {
+        if value.width() != BOARD_WIDTH {
+            return Err(anyhow!("board width must be {BOARD_WIDTH}"));
+        }
         if value.matrix[4] != 0 {
             return Err(anyhow!("board exceeds max height of 4"));
         }
@@ -358,6 +361,10 @@ impl Pack for PcTableChild {
     }
 }
 
+/// Header of `pc-table.bin` files, bump the version whenever the packed
+/// layout of the table changes
+pub const PC_TABLE_FILE: PackFile = PackFile::new(*b"PCTB", 1);
+
 #[derive(Debug, Clone, PartialEq, Eq, Default)]
 pub struct PcTable {
     pub map: HashMap<PcTableKey, TinyVec<[PcTableChild; 2]>>,
@@ -407,8 +414,21 @@ impl PcTable {
             .chain(self.children(board, PieceType::Z))
     }
 
+    /// Load a table from the contents of a `pc-table.bin` file. Tables
+    /// written before files had a header are still accepted, with a warning.
     pub fn load(bytes: &[u8]) -> Result<Self> {
-        Self::unpack(&mut PackCursor::new(bytes))
+        if PcTable::is_legacy(bytes) {
+            eprintln!("warning: pc table has no header and should be regenerated");
+            return PcTable::unpack_bytes(bytes);
+        }
+        PC_TABLE_FILE.unpack(bytes)
+    }
+
+    /// Whether a `pc-table.bin` file was written before files had a header.
+    /// Legacy files start with the entry count, which is never large enough
+    /// to read as the magic bytes.
+    pub fn is_legacy(bytes: &[u8]) -> bool {
+        !bytes.starts_with(&PACK_MAGIC)
     }
 }
 
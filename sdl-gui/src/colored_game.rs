@@ -31,15 +31,15 @@ impl TileColor {
 #[derive(Debug, Clone, Copy)]
 pub struct ColoredGame {
     game: Game,
-    colors: [[TileColor; BOARD_HEIGHT]; BOARD_WIDTH],
+    colors: [[TileColor; BOARD_MAX_HEIGHT]; BOARD_MAX_WIDTH],
 }
 
 impl ColoredGame {
     pub fn new(game: Game) -> Self {
-        let mut colors = [[TileColor::None; BOARD_HEIGHT]; BOARD_WIDTH];
+        let mut colors = [[TileColor::None; BOARD_MAX_HEIGHT]; BOARD_MAX_WIDTH];
 
-        for i in 0..BOARD_WIDTH {
-            for j in 0..BOARD_HEIGHT {
+        for i in 0..game.board.width() {
+            for j in 0..game.board.height() {
                 if game.board.get(i, j) {
                     colors[i][j] = TileColor::Gray;
                 }
@@ -53,12 +53,23 @@ impl ColoredGame {
         &self.game
     }
 
-    fn paint_active_piece(&mut self) {
+    /// Replace the game state, the board colors are kept as they are
+    pub fn set_game(&mut self, game: Game) {
+        self.game = game;
+    }
+
+    /// Paint a locked piece onto the board colors, clearing any lines it
+    /// completes. Used when pieces are locked outside of `apply`
+    pub fn paint_piece(&mut self, piece: &Piece) {
         let game = &self.game;
         let colors = &mut self.colors;
-        let px = game.active.position_x;
-        let py = game.active.position_y;
-        let shape = PieceInfo::shape(game.active.piece_type, game.active.rotation);
+        let width = game.board.width();
+        let height = game.board.height();
+        let px = piece.position_x;
+        let py = piece.position_y;
+        let shape = piece
+            .rotation_system
+            .shape(piece.piece_type, piece.rotation);
         for i in 0..4 {
             for j in 0..4 {
                 if !shape[i as usize][j as usize] {
@@ -66,28 +77,27 @@ impl ColoredGame {
                 }
                 let x = px + i;
                 let y = py + j;
-                if x >= 0 && x < BOARD_WIDTH as i8 && y >= 0 && y <= BOARD_HEIGHT as i8 {
-                    colors[x as usize][y as usize] =
-                        TileColor::from_piece_type(game.active.piece_type);
+                if x >= 0 && x < width as i8 && y >= 0 && y < height as i8 {
+                    colors[x as usize][y as usize] = TileColor::from_piece_type(piece.piece_type);
                 }
             }
         }
 
         let mut lines_cleared = 0;
-        for j in 0..BOARD_HEIGHT {
-            if (0..BOARD_WIDTH)
+        for j in 0..height {
+            if (0..width)
                 .map(|x| colors[x][j])
                 .all(|t| t != TileColor::None)
             {
                 lines_cleared += 1;
             } else {
-                for i in 0..BOARD_WIDTH {
+                for i in 0..width {
                     colors[i][j - lines_cleared] = colors[i][j];
                 }
             }
             for j in 0..lines_cleared {
-                for i in 0..BOARD_WIDTH {
-                    colors[i][BOARD_HEIGHT - lines_cleared + j] = TileColor::None;
+                for i in 0..width {
+                    colors[i][height - lines_cleared + j] = TileColor::None;
                 }
             }
         }
@@ -96,12 +106,14 @@ impl ColoredGame {
     pub fn apply(&mut self, action: Action) -> ActionInfo {
         match action {
             Action::Lock => {
-                self.paint_active_piece();
+                let active = self.game.active;
+                self.paint_piece(&active);
                 self.game.apply(Action::Lock)
             }
             Action::HardDrop => {
                 self.game.apply(Action::SoftDrop);
-                self.paint_active_piece();
+                let active = self.game.active;
+                self.paint_piece(&active);
                 self.game.apply(Action::Lock)
             }
             action => self.game.apply(action),
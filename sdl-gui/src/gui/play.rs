@@ -5,49 +5,28 @@ use anyhow::Result;
 use libtetris::*;
 use sdl2::keyboard::Keycode;
 
-enum ShiftDirection {
-    None,
-    Left,
-    Right,
-}
-
-const SHIFT_DAS: i32 = 10;
-const SHIFT_ARR: i32 = 1;
-const DROP_ARR: i32 = 1;
-const GRAVITY: i32 = 60;
-const LOCK: i32 = 20;
-
 pub struct PlayGui {
-    bag: Bag,
+    history: GameHistory,
+    realtime: Realtime,
     game: ColoredGame,
+    /// The colored game at the start of every piece in the history
+    colors: Vec<ColoredGame>,
     window: Window,
-    shift: ShiftDirection,
-    shift_das: i32,
-    shift_arr: i32,
-    drop: bool,
-    drop_arr: i32,
-    gravity: i32,
-    lock: i32,
     game_over: bool,
 }
 
 impl PlayGui {
     pub fn new() -> Result<Self> {
         let mut bag = Bag::new_rng7(2);
-        let game = ColoredGame::new(Game::from_bag(&mut bag));
+        let game = Game::from_bag(&mut bag);
         let window = Window::new()?;
         Ok(PlayGui {
-            bag,
-            game,
+            history: GameHistory::new(game, bag),
+            realtime: Realtime::new(game, RealtimeConfig::default()),
+            game: ColoredGame::new(game),
+            colors: vec![ColoredGame::new(game)],
             window,
             game_over: true,
-            shift: ShiftDirection::None,
-            drop: false,
-            drop_arr: 0,
-            shift_das: 0,
-            shift_arr: 0,
-            gravity: 0,
-            lock: 0,
         })
     }
 
@@ -61,138 +40,136 @@ impl PlayGui {
     }
 
     fn init(&mut self) {
-        self.bag = Bag::new_rng7(123);
-        self.game = ColoredGame::new(Game::from_bag(&mut self.bag));
+        let mut bag = Bag::new_rng7(123);
+        let game = Game::from_bag(&mut bag);
+        self.history = GameHistory::new(game, bag);
+        self.realtime = Realtime::new(game, RealtimeConfig::default());
+        self.game = ColoredGame::new(game);
+        self.colors = vec![self.game];
         self.game_over = false;
-        self.gravity = GRAVITY;
-        self.lock = LOCK;
-        self.drop = false;
-        self.drop_arr = 0;
-        self.shift = ShiftDirection::None;
-        self.shift_das = 0;
-        self.shift_arr = 0;
     }
 
     fn tick(&mut self) -> Result<()> {
         self.read_input();
+        self.realtime.advance_frames(1);
+        self.sync_game();
+        self.window.draw_colored_game(&self.game)?;
+        Ok(())
+    }
 
-        if self.drop {
-            if self.drop_arr <= 1 {
-                self.game.apply(Action::ShiftDown);
-                self.drop_arr = DROP_ARR;
-            } else {
-                self.drop_arr -= 1;
+    /// Copy the realtime game state into the colored game, and record
+    /// locked pieces in the history
+    fn sync_game(&mut self) {
+        let locks = self.realtime.drain_locks();
+        let start = self.history.piece();
+        let mut synced = true;
+        for lock in locks.iter() {
+            self.game.paint_piece(&lock.piece);
+            synced = synced && self.record_lock(lock);
+            if synced {
+                self.colors.truncate(self.history.piece());
+                self.colors.push(self.game);
             }
         }
-        match self.shift {
-            ShiftDirection::None => {}
-            ShiftDirection::Left | ShiftDirection::Right => {
-                if self.shift_das <= 1 {
-                    if self.shift_arr <= 1 {
-                        if let ShiftDirection::Left = self.shift {
-                            let res = self.game.apply(Action::ShiftLeft);
-                            if res != ActionInfo::Fail {
-                                self.lock = LOCK;
-                            }
-                        } else {
-                            let res = self.game.apply(Action::ShiftRight);
-                            if res != ActionInfo::Fail {
-                                self.lock = LOCK;
-                            }
-                        }
-                        self.shift_arr = SHIFT_ARR;
-                    } else {
-                        self.shift_arr -= 1;
-                    }
-                } else {
-                    self.shift_das -= 1;
-                }
-            }
+        // Finesse can reach a lock by a different route than the player,
+        // such as without holding, which leaves a different game
+        if !locks.is_empty() && !self.history_matches() {
+            synced = false;
+        }
+        if !synced {
+            // A lock can't be reproduced with actions, so the history starts
+            // over from the current game. The realtime queue was last synced
+            // at the start of the frame.
+            self.history.goto(start);
+            self.history.reset(*self.realtime.game());
+            self.colors = vec![self.game];
         }
-        if self.gravity <= 1 {
-            self.game.apply(Action::ShiftDown);
-            self.gravity = GRAVITY;
-        } else {
-            self.gravity -= 1;
+        if !locks.is_empty() {
+            self.realtime.set_queue(self.history.game().queue);
         }
+        self.game.set_game(*self.realtime.game());
+    }
 
-        let should_lock = {
-            let mut game = *self.game.game();
-            game.apply(Action::ShiftDown) == ActionInfo::Fail
+    /// Apply the inputs that lock a piece placed by the realtime engine to
+    /// the history, returns whether the lock matched
+    fn record_lock(&mut self, lock: &LockEvent) -> bool {
+        let game = self.history.game();
+        let Ok(path) = game.finesse(&lock.piece, &FinesseCost::default()) else {
+            return false;
         };
-        if should_lock {
-            if self.lock <= 1 {
-                self.game.apply(Action::Lock);
-                self.lock = LOCK;
-                self.gravity = GRAVITY;
-            } else {
-                self.lock -= 1;
-            }
+        let mut info = ActionInfo::Fail;
+        for action in path.actions {
+            info = self.history.apply(action);
         }
+        info == ActionInfo::Lock(lock.lock_info)
+    }
 
-        self.game.refill_queue(&mut self.bag);
-        self.window.draw_colored_game(&self.game)?;
-        Ok(())
+    /// Whether the history is at the realtime game, apart from the position
+    /// of the active piece. The history may have dealt more of the queue.
+    fn history_matches(&self) -> bool {
+        let history = self.history.game();
+        let realtime = self.realtime.game();
+        let queue_len = realtime.queue.len();
+        history.board == realtime.board
+            && history.active.piece_type == realtime.active.piece_type
+            && history.hold == realtime.hold
+            && history.can_hold == realtime.can_hold
+            && history.combo == realtime.combo
+            && history.back_to_back == realtime.back_to_back
+            && history.queue.len() >= queue_len
+            && history
+                .queue
+                .iter()
+                .take(queue_len)
+                .eq(realtime.queue.iter())
+    }
+
+    /// Go back or forward a piece in the history
+    fn step_history(&mut self, undo: bool) {
+        let moved = match undo {
+            true => self.history.undo(),
+            false => self.history.redo(),
+        };
+        if moved {
+            self.game = self.colors[self.history.piece()];
+            self.realtime.set_game(*self.history.game());
+            self.game.set_game(*self.history.game());
+        }
     }
 
     fn read_input(&mut self) {
         for event in self.window.poll_events() {
-            match event {
+            let input = match event {
                 GuiEvent::Quit | GuiEvent::KeyDown(Keycode::Q) => {
                     self.game_over = true;
+                    continue;
                 }
                 GuiEvent::KeyDown(Keycode::R) => {
                     self.init();
+                    continue;
                 }
-                GuiEvent::KeyDown(Keycode::Left) => {
-                    self.game.apply(Action::ShiftLeft);
-                    self.shift = ShiftDirection::Left;
-                    self.shift_das = SHIFT_DAS;
-                    self.shift_arr = 0;
-                    self.lock = LOCK;
-                }
-                GuiEvent::KeyDown(Keycode::Right) => {
-                    self.game.apply(Action::ShiftRight);
-                    self.shift = ShiftDirection::Right;
-                    self.shift_das = SHIFT_DAS;
-                    self.shift_arr = 0;
-                    self.lock = LOCK;
-                }
-                GuiEvent::KeyUp(Keycode::Left | Keycode::Right) => {
-                    self.shift = ShiftDirection::None;
-                }
-                GuiEvent::KeyDown(Keycode::Down) => {
-                    self.game.apply(Action::ShiftDown);
-                    self.drop = true;
-                    self.drop_arr = DROP_ARR;
-                    self.lock = LOCK;
+                GuiEvent::KeyDown(Keycode::U) => {
+                    self.step_history(true);
+                    continue;
                 }
-                GuiEvent::KeyUp(Keycode::Down) => {
-                    self.drop = false;
+                GuiEvent::KeyDown(Keycode::Y) => {
+                    self.step_history(false);
+                    continue;
                 }
-                GuiEvent::KeyDown(Keycode::Space) => {
-                    self.game.apply(Action::HardDrop);
-                    self.lock = LOCK;
-                    self.gravity = GRAVITY;
-                }
-                GuiEvent::KeyDown(Keycode::Z) => {
-                    self.game.apply(Action::RotateCcw);
-                    self.lock = LOCK;
-                }
-                GuiEvent::KeyDown(Keycode::X) => {
-                    self.game.apply(Action::RotateCw);
-                    self.lock = LOCK;
-                }
-                GuiEvent::KeyDown(Keycode::A) => {
-                    self.game.apply(Action::Rotate180);
-                    self.lock = LOCK;
-                }
-                GuiEvent::KeyDown(Keycode::C) => {
-                    self.game.apply(Action::Hold);
-                    self.lock = LOCK;
-                }
-                _ => {}
-            }
+                GuiEvent::KeyDown(Keycode::Left) => Input::LeftDown,
+                GuiEvent::KeyUp(Keycode::Left) => Input::LeftUp,
+                GuiEvent::KeyDown(Keycode::Right) => Input::RightDown,
+                GuiEvent::KeyUp(Keycode::Right) => Input::RightUp,
+                GuiEvent::KeyDown(Keycode::Down) => Input::SoftDropDown,
+                GuiEvent::KeyUp(Keycode::Down) => Input::SoftDropUp,
+                GuiEvent::KeyDown(Keycode::Space) => Input::HardDrop,
+                GuiEvent::KeyDown(Keycode::Z) => Input::RotateCcw,
+                GuiEvent::KeyDown(Keycode::X) => Input::RotateCw,
+                GuiEvent::KeyDown(Keycode::A) => Input::Rotate180,
+                GuiEvent::KeyDown(Keycode::C) => Input::Hold,
+                _ => continue,
+            };
+            self.realtime.input(input);
         }
     }
 }
@@ -73,6 +73,37 @@ const SIZE: i32 = 60;
 const WIDTH: i32 = 600;
 const HEIGHT: i32 = 750;
 
+/// Position and tile size of a board inside the well, the well fits a 10x24
+/// board at full tile size and smaller tiles are used for larger boards
+struct Layout {
+    size: i32,
+    left: i32,
+    bottom: i32,
+}
+
+impl Layout {
+    fn new(board: &Board) -> Self {
+        let width = board.width() as i32;
+        let height = board.height() as i32;
+        let size = SIZE.min(SIZE * 10 / width).min(SIZE * 24 / height);
+        Layout {
+            size,
+            left: SIZE * 5 + (SIZE * 10 - size * width) / 2,
+            bottom: SIZE * 24,
+        }
+    }
+
+    /// Screen x coordinate of the left edge of a column
+    fn x(&self, x: i32) -> i32 {
+        self.left + x * self.size
+    }
+
+    /// Screen y coordinate of the top edge of a row
+    fn y(&self, y: i32) -> i32 {
+        self.bottom - (y + 1) * self.size
+    }
+}
+
 impl Window {
     fn get_tile_color(tile: TileColor) -> Option<Color> {
         match tile {
@@ -105,32 +136,38 @@ impl Window {
             .map_err(|e| anyhow!("error drawing rect: {e}"))
     }
 
-    fn draw_game_ui(&mut self) -> Result<()> {
+    fn draw_game_ui(&mut self, board: &Board) -> Result<()> {
+        let layout = Layout::new(board);
+        let size = layout.size;
+        let width = board.width() as i32;
+        let visible_height = board.visible_height() as i32;
+
         // Draw grid
         self.canvas.set_draw_color(LIGHT);
-        for i in 5..15 {
-            for j in 4..24 {
-                self.draw_rect(i * SIZE, j * SIZE, SIZE, SIZE)?;
+        for i in 0..width {
+            for j in 0..visible_height {
+                self.draw_rect(layout.x(i), layout.y(j), size, size)?;
             }
         }
 
         // Draw Well
         self.set_draw_color(BLACK);
-        self.fill_rect(SIZE * 4, SIZE * 4, SIZE, SIZE * 21)?;
-        self.fill_rect(SIZE * 5, SIZE * 24, SIZE * 10, SIZE)?;
-        self.fill_rect(SIZE * 15, SIZE * 4, SIZE, SIZE * 21)?;
+        let top = layout.y(visible_height - 1);
+        let wall_height = size * (visible_height + 1);
+        self.fill_rect(layout.x(-1), top, size, wall_height)?;
+        self.fill_rect(layout.x(0), layout.bottom, size * width, size)?;
+        self.fill_rect(layout.x(width), top, size, wall_height)?;
         Ok(())
     }
 
     fn draw_board(&mut self, game: &Game) -> Result<()> {
         // Draw board
+        let layout = Layout::new(&game.board);
         self.set_draw_color(COLOR_GRAY);
-        for i in 0..10 {
-            for j in 0..24 {
+        for i in 0..game.board.width() as i32 {
+            for j in 0..game.board.height() as i32 {
                 if game.board.get(i as usize, j as usize) {
-                    let x = SIZE * 5 + i * SIZE;
-                    let y = SIZE * 23 - j * SIZE;
-                    self.fill_rect(x, y, SIZE, SIZE)?;
+                    self.fill_rect(layout.x(i), layout.y(j), layout.size, layout.size)?;
                 }
             }
         }
@@ -143,18 +180,18 @@ impl Window {
         tile_color: Option<TileColor>,
     ) -> Result<()> {
         // Draw board
-        for i in 0..10 {
-            for j in 0..24 {
+        let board = &game.game().board;
+        let layout = Layout::new(board);
+        for i in 0..board.width() as i32 {
+            for j in 0..board.height() as i32 {
                 let tile = if let Some(tile) = tile_color {
                     tile
                 } else {
                     game.get_tile(i as usize, j as usize)
                 };
                 if let Some(color) = Window::get_tile_color(tile) {
-                    let x = SIZE * 5 + i * SIZE;
-                    let y = SIZE * 23 - j * SIZE;
                     self.set_draw_color(color);
-                    self.fill_rect(x, y, SIZE, SIZE)?;
+                    self.fill_rect(layout.x(i), layout.y(j), layout.size, layout.size)?;
                 }
             }
         }
@@ -163,27 +200,29 @@ impl Window {
 
     fn draw_piece(
         &mut self,
-        piece_type: PieceType,
-        rot: i8,
+        piece: &Piece,
         x: i32,
         y: i32,
+        size: i32,
         tile_color: Option<TileColor>,
     ) -> Result<()> {
         let tile = if let Some(tile) = tile_color {
             tile
         } else {
-            TileColor::from_piece_type(piece_type)
+            TileColor::from_piece_type(piece.piece_type)
         };
         if let Some(color) = Window::get_tile_color(tile) {
             self.canvas.set_draw_color(color);
-            let grid = PieceInfo::shape(piece_type, rot);
+            let grid = piece
+                .rotation_system
+                .shape(piece.piece_type, piece.rotation);
             for i in 0..4 {
                 for j in 0..4 {
                     if grid[i as usize][j as usize] {
-                        let x = x + i * SIZE;
-                        let y = y - j * SIZE;
+                        let x = x + i * size;
+                        let y = y - j * size;
                         self.canvas
-                            .fill_rect(Rect::new(x, y, SIZE as u32, SIZE as u32))
+                            .fill_rect(Rect::new(x, y, size as u32, size as u32))
                             .map_err(|e| anyhow!("{e}"))?;
                     }
                 }
@@ -196,8 +235,9 @@ impl Window {
         self.canvas.set_draw_color(WHITE);
         self.canvas.clear();
 
-        self.draw_game_ui()?;
+        self.draw_game_ui(&game.board)?;
         self.draw_board(game)?;
+        let layout = Layout::new(&game.board);
 
         // Draw ghost piece
         let ghost = {
@@ -205,34 +245,38 @@ impl Window {
             game.apply(Action::SoftDrop);
             game.active
         };
-        let x = SIZE * 5 + ghost.position_x as i32 * SIZE;
-        let y = SIZE * 23 - ghost.position_y as i32 * SIZE;
-        self.draw_piece(
-            ghost.piece_type,
-            ghost.rotation,
-            x,
-            y,
-            Some(TileColor::Ghost),
-        )?;
+        let x = layout.x(ghost.position_x as i32);
+        let y = layout.y(ghost.position_y as i32);
+        self.draw_piece(&ghost, x, y, layout.size, Some(TileColor::Ghost))?;
 
         // Draw current piece
         let active = game.active;
-        let x = SIZE * 5 + active.position_x as i32 * SIZE;
-        let y = SIZE * 23 - active.position_y as i32 * SIZE;
-        self.draw_piece(active.piece_type, active.rotation, x, y, None)?;
+        let x = layout.x(active.position_x as i32);
+        let y = layout.y(active.position_y as i32);
+        self.draw_piece(&active, x, y, layout.size, None)?;
 
         // Draw hold
         if let Some(hold) = game.hold {
             let x = 0;
             let y = SIZE * 4;
-            self.draw_piece(hold, 0, x, y, None)?;
+            let piece = Piece {
+                piece_type: hold,
+                rotation: 0,
+                ..active
+            };
+            self.draw_piece(&piece, x, y, SIZE, None)?;
         }
 
         // Draw queue
         for (idx, piece) in game.queue.iter().take(5).enumerate() {
             let x = SIZE * 16;
             let y = SIZE * (8 + idx as i32 * 4);
-            self.draw_piece(piece, 0, x, y, None)?;
+            let piece = Piece {
+                piece_type: piece,
+                rotation: 0,
+                ..active
+            };
+            self.draw_piece(&piece, x, y, SIZE, None)?;
         }
 
         self.canvas.present();
@@ -243,8 +287,9 @@ impl Window {
         self.canvas.set_draw_color(WHITE);
         self.canvas.clear();
 
-        self.draw_game_ui()?;
+        self.draw_game_ui(&game.game().board)?;
         self.draw_colored_board(game, None)?;
+        let layout = Layout::new(&game.game().board);
 
         // Draw ghost piece
         let ghost = {
@@ -252,34 +297,38 @@ impl Window {
             game.apply(Action::SoftDrop);
             game.game().active
         };
-        let x = SIZE * 5 + ghost.position_x as i32 * SIZE;
-        let y = SIZE * 23 - ghost.position_y as i32 * SIZE;
-        self.draw_piece(
-            ghost.piece_type,
-            ghost.rotation,
-            x,
-            y,
-            Some(TileColor::Ghost),
-        )?;
+        let x = layout.x(ghost.position_x as i32);
+        let y = layout.y(ghost.position_y as i32);
+        self.draw_piece(&ghost, x, y, layout.size, Some(TileColor::Ghost))?;
 
         // Draw current piece
         let active = game.game().active;
-        let x = SIZE * 5 + active.position_x as i32 * SIZE;
-        let y = SIZE * 23 - active.position_y as i32 * SIZE;
-        self.draw_piece(active.piece_type, active.rotation, x, y, None)?;
+        let x = layout.x(active.position_x as i32);
+        let y = layout.y(active.position_y as i32);
+        self.draw_piece(&active, x, y, layout.size, None)?;
 
         // Draw hold
         if let Some(hold) = game.game().hold {
             let x = 0;
             let y = SIZE * 8;
-            self.draw_piece(hold, 0, x, y, None)?;
+            let piece = Piece {
+                piece_type: hold,
+                rotation: 0,
+                ..active
+            };
+            self.draw_piece(&piece, x, y, SIZE, None)?;
         }
 
         // Draw queue
         for (idx, piece) in game.game().queue.iter().take(5).enumerate() {
             let x = SIZE * 16;
             let y = SIZE * (8 + idx as i32 * 4);
-            self.draw_piece(piece, 0, x, y, None)?;
+            let piece = Piece {
+                piece_type: piece,
+                rotation: 0,
+                ..active
+            };
+            self.draw_piece(&piece, x, y, SIZE, None)?;
         }
 
         self.canvas.present();
@@ -1,6 +1,6 @@
 use libtetris::Ai;
-use tree_bot::{TreeAi, DEFAULT_PARAMS};
+use tree_bot::{Params, Search, TreeAi};
 
 fn main() {
-    TreeAi::new(4, 6, DEFAULT_PARAMS).demo();
+    TreeAi::new(Search::Dfs { depth: 4, take: 6 }, Params::default()).demo();
 }
@@ -1,8 +1,15 @@
 use anyhow::Result;
 use sdl_gui::AiGui;
-use tree_bot::{TreeAi, DEFAULT_PARAMS};
+use std::time::Duration;
+use tree_bot::{Budget, Params, Search, TreeAi};
 
 fn main() -> Result<()> {
-    AiGui::new(TreeAi::new(4, 10, DEFAULT_PARAMS))?.run()?;
+    // Search as deep as possible within a frame or so
+    let budget = Budget {
+        time: Some(Duration::from_millis(20)),
+        ..Default::default()
+    };
+    let search = Search::Dfs { depth: 6, take: 10 };
+    AiGui::new(TreeAi::new(search, Params::default()).with_budget(budget))?.run()?;
     Ok(())
 }
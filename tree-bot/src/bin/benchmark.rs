@@ -0,0 +1,23 @@
+use anyhow::Result;
+use libtetris::Harness;
+use std::{env, fs};
+use tree_bot::{Params, Search, TreeAi};
+
+// Usage: benchmark [output.json | output.csv] [weights file]
+// Prints a JSON report if no output path is given, and uses the default
+// params if no weights file is given
+
+fn main() -> Result<()> {
+    let params = match env::args().nth(2) {
+        Some(path) => Params::load(path)?,
+        None => Params::default(),
+    };
+    let harness = Harness::new((0..10).collect(), 500);
+    let report = harness.run(|| TreeAi::new(Search::Dfs { depth: 4, take: 6 }, params.clone()));
+    match env::args().nth(1) {
+        Some(path) if path.ends_with(".csv") => fs::write(path, report.to_csv())?,
+        Some(path) => fs::write(path, report.to_json()?)?,
+        None => println!("{}", report.to_json()?),
+    }
+    Ok(())
+}
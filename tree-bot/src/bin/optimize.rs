@@ -1,9 +1,24 @@
-use tree_bot::Optimizer;
+use anyhow::Result;
+use std::env;
+use tree_bot::{Optimizer, Params};
 
-fn main() {
-    let mut optimizer = Optimizer::new(0);
+// Usage: optimize [template weights file] [output weights file]
+// Tunes the features of the default params if no template is given, the
+// best params are saved to the output after every epoch
+
+fn main() -> Result<()> {
+    let args = env::args().collect::<Vec<_>>();
+    let template = match args.get(1) {
+        Some(path) => Params::load(path)?,
+        None => Params::default(),
+    };
+    let mut optimizer = Optimizer::new(0, template);
     optimizer.init();
     for _ in 0..12 {
         optimizer.perform_epoch();
+        if let Some(path) = args.get(2) {
+            optimizer.best().save(path)?;
+        }
     }
+    Ok(())
 }
@@ -0,0 +1,154 @@
+use anyhow::Result;
+use libtetris::{Ai, SimpleAi, Versus};
+use pc_finder::{PcFinderAi, PcTable};
+use std::{env, fs};
+use tree_bot::{Params, Search, TreeAi};
+
+// Usage: versus [matches] [path to pc-table.bin]
+// PcFinderAi is left out if no pc table is given
+
+const ELO_START: f32 = 1500.;
+const ELO_K: f32 = 32.;
+
+struct Player {
+    name: &'static str,
+    create: Box<dyn Fn() -> Box<dyn Ai>>,
+    elo: f32,
+    wins: u32,
+    losses: u32,
+    draws: u32,
+    apm: f32,
+    pps: f32,
+    lines_sent: u32,
+    matches: u32,
+}
+
+impl Player {
+    fn new(name: &'static str, create: impl Fn() -> Box<dyn Ai> + 'static) -> Self {
+        Player {
+            name,
+            create: Box::new(create),
+            elo: ELO_START,
+            wins: 0,
+            losses: 0,
+            draws: 0,
+            apm: 0.,
+            pps: 0.,
+            lines_sent: 0,
+            matches: 0,
+        }
+    }
+}
+
+fn main() -> Result<()> {
+    let args = env::args().collect::<Vec<_>>();
+    let matches = match args.get(1) {
+        Some(arg) => arg.parse()?,
+        None => 10,
+    };
+
+    let mut players = vec![
+        Player::new("SimpleAi", || Box::new(SimpleAi::new())),
+        Player::new("TreeAi", || {
+            Box::new(TreeAi::new(
+                Search::Dfs { depth: 4, take: 6 },
+                Params::default(),
+            ))
+        }),
+        Player::new("TreeAiBeam", || {
+            Box::new(TreeAi::new(
+                Search::Beam {
+                    depth: 6,
+                    width: 100,
+                },
+                Params::default(),
+            ))
+        }),
+        Player::new("TreeAiBest", || {
+            Box::new(TreeAi::new(
+                Search::BestFirst { nodes: 1000 },
+                Params::default(),
+            ))
+        }),
+    ];
+    if let Some(path) = args.get(2) {
+        let pc_table = PcTable::load(&fs::read(path)?)?;
+        players.push(Player::new("PcFinderAi", move || {
+            Box::new(PcFinderAi::new(pc_table.clone()))
+        }));
+    }
+
+    for a in 0..players.len() {
+        for b in (a + 1)..players.len() {
+            for seed in 0..matches {
+                let mut ai_a = (players[a].create)();
+                let mut ai_b = (players[b].create)();
+                // The first player wins when both top out on the same turn,
+                // so seats are swapped on odd seeds
+                let versus = Versus::new(seed);
+                let result = match seed % 2 {
+                    0 => versus.run(ai_a.as_mut(), ai_b.as_mut()),
+                    _ => versus.run(ai_b.as_mut(), ai_a.as_mut()).swapped(),
+                };
+
+                let score_a = match result.winner {
+                    Some(0) => 1.,
+                    Some(_) => 0.,
+                    None => 0.5,
+                };
+                let expected_a = 1. / (1. + 10f32.powf((players[b].elo - players[a].elo) / 400.));
+                let delta = ELO_K * (score_a - expected_a);
+                players[a].elo += delta;
+                players[b].elo -= delta;
+
+                for (i, stats) in [(a, &result.stats[0]), (b, &result.stats[1])] {
+                    let player = &mut players[i];
+                    match result.winner {
+                        None => player.draws += 1,
+                        Some(w) if (w == 0) == (i == a) => player.wins += 1,
+                        Some(_) => player.losses += 1,
+                    }
+                    player.apm += stats.apm();
+                    player.pps += stats.pps();
+                    player.lines_sent += stats.lines_sent;
+                    player.matches += 1;
+                }
+
+                let winner = match result.winner {
+                    Some(0) => players[a].name,
+                    Some(_) => players[b].name,
+                    None => "draw",
+                };
+                println!(
+                    "{} vs {} (seed {seed}): {winner}, sent {}-{}",
+                    players[a].name,
+                    players[b].name,
+                    result.stats[0].lines_sent,
+                    result.stats[1].lines_sent
+                );
+            }
+        }
+    }
+
+    players.sort_by(|a, b| b.elo.total_cmp(&a.elo));
+    println!();
+    println!(
+        "{:<12} {:>6} {:>4} {:>4} {:>4} {:>8} {:>8} {:>6}",
+        "Name", "Elo", "W", "L", "D", "APM", "PPS", "Sent"
+    );
+    for player in players.iter() {
+        let matches = player.matches.max(1) as f32;
+        println!(
+            "{:<12} {:>6.0} {:>4} {:>4} {:>4} {:>8.1} {:>8.1} {:>6}",
+            player.name,
+            player.elo,
+            player.wins,
+            player.losses,
+            player.draws,
+            player.apm / matches,
+            player.pps / matches,
+            player.lines_sent
+        );
+    }
+    Ok(())
+}
@@ -2,37 +2,82 @@ mod optimizer;
 mod param;
 mod tree;
 
+use anyhow::Result;
 use libtetris::*;
 pub use optimizer::*;
 pub use param::*;
+use std::path::Path;
 pub use tree::*;
 
 pub struct TreeAi {
-    pub depth: usize,
-    pub take: usize,
     pub step: usize,
+    /// With a budget, searches deepen one piece at a time until the budget
+    /// runs out, and the deepest complete search is used
+    pub budget: Option<Budget>,
     tree: Tree,
 }
 
 impl TreeAi {
-    pub fn new(depth: usize, take: usize, params: Params) -> Self {
-        assert!(depth >= 1);
+    pub fn new(search: Search, params: Params) -> Self {
+        match search {
+            Search::Dfs { depth, .. } | Search::Beam { depth, .. } => assert!(depth >= 1),
+            Search::BestFirst { .. } => {}
+        }
         TreeAi {
-            depth,
-            take,
             step: 0,
-            tree: Tree::new(params, depth, take),
+            budget: None,
+            tree: Tree::new(params, search),
         }
     }
+
+    pub fn with_budget(mut self, budget: Budget) -> Self {
+        self.budget = Some(budget);
+        self
+    }
+
+    /// Create a TreeAi with params loaded from a JSON or TOML weights file
+    pub fn from_file(search: Search, path: impl AsRef<Path>) -> Result<Self> {
+        Ok(TreeAi::new(search, Params::load(path)?))
+    }
+
+    pub fn search(&self) -> Search {
+        self.tree.search
+    }
+
+    /// Search one piece deeper at a time up to `max_depth`, returns the
+    /// scores of the deepest search that finished within the budget and the
+    /// queue
+    fn deepen(
+        &mut self,
+        roots: &[Node],
+        max_depth: usize,
+        search: impl Fn(usize) -> Search,
+    ) -> Result<(Vec<f32>, usize)> {
+        let mut best = self.tree.search_with(search(1), roots)?;
+        for depth in 2..=max_depth {
+            match self.tree.search_with(search(depth), roots) {
+                // Searching deeper won't help once the queue runs out
+                Ok(result) if result.1 < depth => {
+                    best = result;
+                    break;
+                }
+                Ok(result) => best = result,
+                Err(err) if err.is::<BudgetExhausted>() => break,
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(best)
+    }
 }
 
 impl Ai for TreeAi {
     fn evaluate(&mut self, game: &Game) -> Evaluation {
-        // The AI always expects there to be a hold piece
-        if game.hold.is_none() {
+        // The AI expects there to be a hold piece, unless hold is disabled
+        if game.hold.is_none() && !game.hold_disabled {
             return Evaluation::Success {
                 actions: vec![Action::Hold],
                 score: 0.,
+                stats: None,
             };
         }
 
@@ -52,16 +97,45 @@ impl Ai for TreeAi {
         }
 
         let children = game.children(Fin::Simple1);
+        let roots = children
+            .iter()
+            .map(|child| {
+                let score = self.tree.params.eval_node(&child.game.board);
+                Node::new(child.game, self.step + 1, score)
+            })
+            .collect::<Vec<_>>();
+        self.tree.set_budget(self.budget);
+        let result = match (self.budget, self.tree.search) {
+            (Some(_), Search::Dfs { depth, take }) => {
+                self.deepen(&roots, depth, |depth| Search::Dfs { depth, take })
+            }
+            (Some(_), Search::Beam { depth, width }) => {
+                self.deepen(&roots, depth, |depth| Search::Beam { depth, width })
+            }
+            (_, search) => self.tree.search_with(search, &roots),
+        };
+        let (node_scores, depth) = match result {
+            Ok(result) => result,
+            Err(err) if err.is::<BudgetExhausted>() => {
+                return Evaluation::Fail {
+                    message: "Search budget exhausted".to_string(),
+                };
+            }
+            Err(_) => {
+                return Evaluation::Fail {
+                    message: "Queue not sufficiently long".to_string(),
+                };
+            }
+        };
+        let stats = SearchStats {
+            depth: depth as u32,
+            nodes: self.tree.nodes as u64,
+        };
 
         let mut best_score = f32::NEG_INFINITY;
         let mut best_child = None;
-        for child in children {
+        for (child, node_score) in children.into_iter().zip(node_scores) {
             let edge_score = self.tree.params.eval_edge(&child.lock_info);
-            let Ok(node_score) = self.tree.dfs_game(&child.game, self.step + 1) else {
-                return Evaluation::Fail {
-                    message: "Queue not sufficiently long".to_string(),
-                };
-            };
             let score = edge_score + node_score;
             if best_child.is_none() || score > best_score {
                 best_child = Some(child);
@@ -75,6 +149,7 @@ impl Ai for TreeAi {
             Some(child) => Evaluation::Success {
                 actions: child.actions().collect(),
                 score: best_score,
+                stats: Some(stats),
             },
             None => Evaluation::Fail {
                 message: "No valid moves".to_string(),
@@ -82,3 +157,143 @@ impl Ai for TreeAi {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::Duration;
+
+    /// A game with the given rows, playing the pieces in order and holding
+    /// the last one
+    pub(crate) fn test_game(rows: &[u16], pieces: &[PieceType]) -> Game {
+        let mut board = Board::new();
+        for (y, &row) in rows.iter().enumerate() {
+            board.set_row(y, row);
+        }
+        let (&hold, pieces) = pieces.split_last().unwrap();
+        let active = Piece::spawn(pieces[0], Default::default(), &board);
+        Game::from_parts(board, active, Some(hold), &pieces[1..], true)
+    }
+
+    /// Play the move chosen by a TreeAi
+    fn play_best(game: &Game, search: Search) -> Game {
+        let mut game = *game;
+        let Evaluation::Success { actions, .. } =
+            TreeAi::new(search, Params::default()).evaluate(&game)
+        else {
+            panic!("evaluation failed");
+        };
+        for action in actions {
+            assert_ne!(game.apply(action), ActionInfo::Fail);
+        }
+        game
+    }
+
+    #[test]
+    fn test_tree_ai_evaluate() {
+        let searches = [
+            Search::Dfs { depth: 2, take: 10 },
+            Search::Beam {
+                depth: 3,
+                width: 20,
+            },
+            Search::BestFirst { nodes: 50 },
+        ];
+        for search in searches {
+            // An O piece shouldn't be placed over the gap next to column 0
+            let game = test_game(&[0b1], &[PieceType::O; 5]);
+            let game = play_best(&game, search);
+            assert_eq!(game.board.holes().iter().sum::<i8>(), 0);
+            assert_eq!(game.board.max_height(), 2);
+
+            // An I piece that can't be held should take the tetris in the
+            // well on column 9
+            let row = (1 << 9) - 1;
+            let mut pieces = [PieceType::O; 5];
+            pieces[0] = PieceType::I;
+            let mut game = test_game(&[row; 4], &pieces);
+            game.hold = None;
+            game.hold_disabled = true;
+            let game = play_best(&game, search);
+            assert_eq!(game.board.max_height(), 0);
+        }
+    }
+
+    #[test]
+    fn test_tree_ai_budget() {
+        let game = test_game(&[0b1], &[PieceType::O; 5]);
+        let search = Search::Dfs { depth: 3, take: 4 };
+        let stats = |budget: Option<Budget>| {
+            let mut tree_ai = TreeAi::new(search, Params::default());
+            tree_ai.budget = budget;
+            match tree_ai.evaluate(&game) {
+                Evaluation::Success { stats, .. } => stats.unwrap(),
+                Evaluation::Fail { message } => panic!("{message}"),
+            }
+        };
+
+        // A search without a budget expands each root and the best 4 of its
+        // children
+        let full = stats(None);
+        assert_eq!(full.depth, 3);
+        assert_eq!(full.nodes % 5, 0);
+
+        // Deepening one piece at a time repeats the shallower searches
+        let budget = Budget {
+            nodes: Some(usize::MAX),
+            ..Default::default()
+        };
+        let deepened = stats(Some(budget));
+        assert_eq!(deepened.depth, 3);
+        assert_eq!(deepened.nodes, full.nodes + full.nodes / 5);
+
+        // Running out of budget falls back to shallower searches, down to
+        // only scoring the roots
+        let budget = Budget {
+            nodes: Some(full.nodes as usize),
+            ..Default::default()
+        };
+        assert_eq!(stats(Some(budget)).depth, 2);
+        let budget = Budget {
+            time: Some(Duration::ZERO),
+            ..Default::default()
+        };
+        assert_eq!(stats(Some(budget)), SearchStats { depth: 1, nodes: 0 });
+
+        // Best first search stops early instead of failing
+        let mut tree_ai =
+            TreeAi::new(Search::BestFirst { nodes: 1000 }, Params::default()).with_budget(budget);
+        let Evaluation::Success { stats, .. } = tree_ai.evaluate(&game) else {
+            panic!("evaluation failed");
+        };
+        assert_eq!(stats, Some(SearchStats { depth: 1, nodes: 0 }));
+    }
+
+    #[test]
+    fn test_tree_ai_previewless() {
+        // Only the current piece is known, so lines end after placing it
+        let mut bag = Bag::new_rng7(0);
+        let mut game = Game::from_bag_with_preview(&mut bag, 0);
+        let mut tree_ai = TreeAi::new(Search::Dfs { depth: 4, take: 6 }, Params::default());
+        let mut locks = 0;
+        while locks < 30 {
+            let Evaluation::Success { actions, stats, .. } = tree_ai.evaluate(&game) else {
+                panic!("evaluation failed");
+            };
+            if let Some(stats) = stats {
+                assert_eq!(stats.depth, 1);
+            }
+            for action in actions {
+                match game.apply(action) {
+                    ActionInfo::Success => {}
+                    ActionInfo::Lock(lock_info) => {
+                        assert!(!lock_info.top_out);
+                        locks += 1;
+                    }
+                    ActionInfo::Fail => panic!("invalid action {action:?}"),
+                }
+            }
+            game.refill_queue(&mut bag);
+        }
+    }
+}
@@ -9,7 +9,7 @@ use rand_distr::{Distribution, Normal, Uniform};
 use rand_xorshift::XorShiftRng;
 use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
 
-use crate::{Params, TreeAi, PARAMS_DIM};
+use crate::{Params, Search, TreeAi};
 
 const POPULATION_KEEP: usize = 100;
 const POPULATION_NEW: usize = 100;
@@ -18,21 +18,29 @@ const POPULATION_COMBINE_ALL: usize = 300;
 const POPULATION_SIZE: usize =
     POPULATION_KEEP + POPULATION_NEW + POPULATION_COMBINE_KEEP + POPULATION_COMBINE_ALL;
 
+/// Searches for weights of the features in a template [`Params`]
 pub struct Optimizer {
     epoch: i32,
     rng: XorShiftRng,
+    template: Params,
     population: Vec<Params>,
 }
 
 impl Optimizer {
-    pub fn new(seed: u64) -> Self {
+    pub fn new(seed: u64, template: Params) -> Self {
         Optimizer {
             epoch: 0,
             rng: XorShiftRng::seed_from_u64(seed),
+            template,
             population: Vec::new(),
         }
     }
 
+    /// The best params as of the last ranking
+    pub fn best(&self) -> &Params {
+        &self.population[0]
+    }
+
     pub fn init(&mut self) {
         self.epoch = 0;
         for _ in 0..POPULATION_SIZE {
@@ -48,7 +56,7 @@ impl Optimizer {
         // Rank the population
         let (best, avg) = self.rank_population();
         println!("Epoch {}: best={} avg={}", self.epoch, best, avg);
-        println!("Best: {:?}", self.population[0]);
+        println!("Best: {:?}", self.best());
 
         // Keep population best
         let mut new_population = Vec::new();
@@ -67,11 +75,12 @@ impl Optimizer {
             let vec1 = self.population[idx1].to_vec();
             let vec2 = self.population[idx2].to_vec();
             let weight = uniform.sample(&mut self.rng);
-            let mut new_vec = [0.; PARAMS_DIM];
-            for i in 0..PARAMS_DIM {
-                new_vec[i] = vec1[i] * weight + vec2[i] * (1. - weight);
-            }
-            new_population.push(Params::from_vec(new_vec));
+            let new_vec = vec1
+                .iter()
+                .zip(vec2.iter())
+                .map(|(a, b)| a * weight + b * (1. - weight))
+                .collect::<Vec<_>>();
+            new_population.push(self.template.with_vec(&new_vec));
         }
 
         // Combine all
@@ -81,11 +90,12 @@ impl Optimizer {
             let vec1 = self.population[idx1].to_vec();
             let vec2 = self.population[idx2].to_vec();
             let weight = uniform.sample(&mut self.rng);
-            let mut new_vec = [0.; PARAMS_DIM];
-            for i in 0..PARAMS_DIM {
-                new_vec[i] = vec1[i] * weight + vec2[i] * (1. - weight);
-            }
-            new_population.push(Params::from_vec(new_vec));
+            let new_vec = vec1
+                .iter()
+                .zip(vec2.iter())
+                .map(|(a, b)| a * weight + b * (1. - weight))
+                .collect::<Vec<_>>();
+            new_population.push(self.template.with_vec(&new_vec));
         }
 
         self.population = new_population
@@ -100,7 +110,7 @@ impl Optimizer {
             .population
             .par_iter()
             .map(|params| {
-                let fitness = Self::compute_fitness(*params, seed);
+                let fitness = Self::compute_fitness(params, seed);
                 let val = count.fetch_add(1, Ordering::Relaxed) + 1;
                 print!("\rRanking {val}/{POPULATION_SIZE}");
                 std::io::stdout().flush().unwrap();
@@ -130,24 +140,17 @@ impl Optimizer {
     pub fn random_params(&mut self) -> Params {
         let normal = Normal::new(0., 1.).unwrap();
 
-        let mut vec = [0.0; PARAMS_DIM];
-        for i in 0..PARAMS_DIM {
-            vec[i] = normal.sample(&mut self.rng);
-        }
+        let vec = (0..self.template.dim())
+            .map(|_| normal.sample(&mut self.rng))
+            .collect::<Vec<_>>();
 
-        Params::from_vec(vec)
+        self.template.with_vec(&vec)
     }
 
-    pub fn compute_fitness(params: Params, seed: u64) -> i32 {
-        let mut lc1 = 0;
-        let mut lc2 = 0;
-        let mut lc3 = 0;
-        let mut lc4 = 0;
-        let mut ts1 = 0;
-        let mut ts2 = 0;
-        let mut ts3 = 0;
+    pub fn compute_fitness(params: &Params, seed: u64) -> i32 {
+        let mut attack = 0;
 
-        let mut tree_ai = TreeAi::new(4, 10, params);
+        let mut tree_ai = TreeAi::new(Search::Dfs { depth: 4, take: 10 }, params.clone());
         let mut bag = Bag::new_rng7(seed);
         let mut game = Game::from_bag(&mut bag);
         'outer: for _ in 0..800 {
@@ -164,22 +167,13 @@ impl Optimizer {
                         if lock_info.top_out {
                             break;
                         }
-                        match (lock_info.tspin, lock_info.lines_cleared) {
-                            (true, 1) => ts1 += 1,
-                            (true, 2) => ts2 += 1,
-                            (true, 3) => ts3 += 1,
-                            (false, 1) => lc1 += 1,
-                            (false, 2) => lc2 += 1,
-                            (false, 3) => lc3 += 1,
-                            (false, 4) => lc4 += 1,
-                            _ => {}
-                        }
+                        attack += lock_info.attack as i32;
                     }
                     ActionInfo::Fail => break 'outer,
                 }
             }
             game.refill_queue(&mut bag);
         }
-        ts1 * 2 + ts2 * 4 + ts3 * 6 + lc1 * 0 + lc2 * 1 + lc3 * 2 + lc4 * 4
+        attack
     }
 }
@@ -1,45 +1,105 @@
-use libtetris::{Board, LockInfo};
+use anyhow::{bail, Result};
+use libtetris::{Board, LockInfo, SpinKind};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fs, path::Path};
 
-#[derive(Debug, Clone, Copy)]
+/// A board feature that can be weighted in [`Params`], named in kebab case
+/// in weight files
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Feature {
+    /// Square of the height of the tallest column
+    MaxHeightSquared,
+    /// Sum of squared height differences between adjacent columns
+    BumpinessSquared,
+    /// Number of empty tiles with a filled tile above them
+    Holes,
+    /// Height of the tallest column
+    MaxHeight,
+    /// Sum of height differences between adjacent columns
+    Bumpiness,
+    RowTransitions,
+    ColumnTransitions,
+    RowsWithHoles,
+    CoveredCells,
+    HoleDepth,
+    Overhangs,
+    WellSums,
+    Parity,
+    TsdSlots,
+    TstSlots,
+}
+
+impl Feature {
+    pub fn eval(self, board: &Board) -> f32 {
+        let bumpiness = |pow: u32| {
+            let height_map = board.height_map();
+            height_map
+                .windows(2)
+                .map(|x| (x[0] as i32 - x[1] as i32).abs().pow(pow))
+                .sum::<i32>()
+        };
+        let value = match self {
+            Feature::MaxHeightSquared => board.max_height().pow(2) as i32,
+            Feature::BumpinessSquared => bumpiness(2),
+            Feature::Holes => board.holes().into_iter().map(|x| x as i32).sum(),
+            Feature::MaxHeight => board.max_height() as i32,
+            Feature::Bumpiness => bumpiness(1),
+            Feature::RowTransitions => board.row_transitions() as i32,
+            Feature::ColumnTransitions => board.column_transitions() as i32,
+            Feature::RowsWithHoles => board.rows_with_holes() as i32,
+            Feature::CoveredCells => board.covered_cells() as i32,
+            Feature::HoleDepth => board.hole_depth() as i32,
+            Feature::Overhangs => board.overhangs() as i32,
+            Feature::WellSums => board.well_sums() as i32,
+            Feature::Parity => board.parity() as i32,
+            Feature::TsdSlots => board.t_slots(2) as i32,
+            Feature::TstSlots => board.t_slots(3) as i32,
+        };
+        value as f32
+    }
+}
+
+/// Number of attack weights in [`Params::to_vec`]
+const ATTACK_DIM: usize = 4;
+
+/// Evaluator weights. Nodes are scored by a weighted sum of board features,
+/// and edges by the lines they clear and the attack they send.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
 pub struct Params {
-    // Node
-    pub max_height: f32,
-    pub bumpiness: f32,
-    pub holes: f32,
     // Edge
     pub normal_clear: [f32; 5],
     pub tspin_clear: [f32; 4],
+    /// Weight of each line of attack sent
+    #[serde(default)]
+    pub attack: f32,
+    /// Weight of a clear that continues a back-to-back chain
+    #[serde(default)]
+    pub back_to_back: f32,
+    /// Weight of each consecutive clear in a combo
+    #[serde(default)]
+    pub combo: f32,
+    /// Weight of a clear that empties the board
+    #[serde(default)]
+    pub perfect_clear: f32,
+    // Node
+    pub features: BTreeMap<Feature, f32>,
 }
 
-pub const PARAMS_DIM: usize = 12;
-
 impl Params {
     pub fn eval_node(&self, board: &Board) -> f32 {
-        // Board height
-        let max_height = board.max_height().pow(2);
-
-        // Board Bumpiness
-        let mut bumpiness = 0;
-        let height_map = board.height_map();
-        for x in height_map.windows(2) {
-            bumpiness += (x[0] as i32 - x[1] as i32).pow(2);
-        }
-
-        // Board holes
-        let mut holes = 0;
-        for x in board.holes() {
-            holes += x as i32;
-        }
-
-        (self.max_height * max_height as f32)
-            + (self.bumpiness * bumpiness as f32)
-            + (self.holes * holes as f32)
+        self.features
+            .iter()
+            .map(|(feature, weight)| weight * feature.eval(board))
+            .sum()
     }
 
     pub fn eval_edge(&self, lock_info: &LockInfo) -> f32 {
         if lock_info.top_out {
-            f32::NEG_INFINITY
-        } else if lock_info.tspin {
+            return f32::NEG_INFINITY;
+        }
+        let clear = if lock_info.spin == SpinKind::Full {
             *self
                 .tspin_clear
                 .get(lock_info.lines_cleared as usize)
@@ -49,48 +109,162 @@ impl Params {
                 .normal_clear
                 .get(lock_info.lines_cleared as usize)
                 .unwrap_or(&0.)
-        }
+        };
+        clear
+            + self.attack * lock_info.attack as f32
+            + self.back_to_back * lock_info.back_to_back as u8 as f32
+            + self.combo * lock_info.combo as f32
+            + self.perfect_clear * lock_info.perfect_clear as u8 as f32
     }
 
-    pub fn from_vec(vec: [f32; PARAMS_DIM]) -> Self {
+    /// Number of weights, the length of [`Params::to_vec`]
+    pub fn dim(&self) -> usize {
+        self.features.len() + self.normal_clear.len() + self.tspin_clear.len() + ATTACK_DIM
+    }
+
+    /// All weights, the feature weights in order followed by the normal
+    /// and t-spin clear weights, and the attack, back-to-back, combo and
+    /// perfect clear weights
+    pub fn to_vec(&self) -> Vec<f32> {
+        let mut vec = self.features.values().copied().collect::<Vec<_>>();
+        vec.extend(self.normal_clear);
+        vec.extend(self.tspin_clear);
+        vec.extend([
+            self.attack,
+            self.back_to_back,
+            self.combo,
+            self.perfect_clear,
+        ]);
+        vec
+    }
+
+    /// Params with the same features as these, with weights taken from a
+    /// vector laid out as in [`Params::to_vec`]
+    pub fn with_vec(&self, vec: &[f32]) -> Params {
+        assert_eq!(vec.len(), self.dim());
+        let (features, clears) = vec.split_at(self.features.len());
+        let (normal_clear, clears) = clears.split_at(self.normal_clear.len());
+        let (tspin_clear, attack) = clears.split_at(self.tspin_clear.len());
         Params {
-            max_height: vec[0],
-            bumpiness: vec[1],
-            holes: vec[2],
-            normal_clear: [vec[3], vec[4], vec[5], vec[6], vec[7]],
-            tspin_clear: [vec[8], vec[9], vec[10], vec[11]],
+            normal_clear: normal_clear.try_into().unwrap(),
+            tspin_clear: tspin_clear.try_into().unwrap(),
+            attack: attack[0],
+            back_to_back: attack[1],
+            combo: attack[2],
+            perfect_clear: attack[3],
+            features: self
+                .features
+                .keys()
+                .copied()
+                .zip(features.iter().copied())
+                .collect(),
         }
     }
 
-    pub fn to_vec(&self) -> [f32; PARAMS_DIM] {
-        [
-            self.max_height,
-            self.bumpiness,
-            self.holes,
-            self.normal_clear[0],
-            self.normal_clear[1],
-            self.normal_clear[2],
-            self.normal_clear[3],
-            self.normal_clear[4],
-            self.tspin_clear[0],
-            self.tspin_clear[1],
-            self.tspin_clear[2],
-            self.tspin_clear[3],
-        ]
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn from_json(text: &str) -> Result<Self> {
+        Ok(serde_json::from_str(text)?)
+    }
+
+    pub fn to_toml(&self) -> Result<String> {
+        Ok(toml::to_string(self)?)
+    }
+
+    pub fn from_toml(text: &str) -> Result<Self> {
+        Ok(toml::from_str(text)?)
+    }
+
+    /// Load a weights file, the format is picked by the `.json` or `.toml`
+    /// extension
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)?;
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => Params::from_json(&text),
+            Some("toml") => Params::from_toml(&text),
+            _ => bail!("unknown weights file format {}", path.display()),
+        }
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let text = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => self.to_json()?,
+            Some("toml") => self.to_toml()?,
+            _ => bail!("unknown weights file format {}", path.display()),
+        };
+        fs::write(path, text)?;
+        Ok(())
     }
 }
 
-// Found using optimizer search
-pub static DEFAULT_PARAMS: Params = Params {
-    max_height: 0.007564539,
-    bumpiness: -0.119221255,
-    holes: -1.3969069,
-    normal_clear: [
-        0.081128635,
-        -0.22556686,
-        0.035156228,
-        0.43860132,
-        0.35975468,
-    ],
-    tspin_clear: [-0.23875281, 0.26708335, 0.6625228, -1.0724338],
-};
+impl Default for Params {
+    /// Weights found using optimizer search
+    fn default() -> Self {
+        Params::from_toml(include_str!("../params/default.toml")).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_params() {
+        let params = Params::default();
+        assert_eq!(params.features.len(), 3);
+        assert_eq!(params.dim(), 16);
+        assert_eq!(params.with_vec(&params.to_vec()), params);
+        assert_eq!(
+            Params::from_json(&params.to_json().unwrap()).unwrap(),
+            params
+        );
+        assert_eq!(
+            Params::from_toml(&params.to_toml().unwrap()).unwrap(),
+            params
+        );
+
+        // A column of height 4 next to a hole
+        let mut board = Board::new();
+        board.set_row(0, 0b01);
+        board.set_row(1, 0b11);
+        board.set_row(2, 0b01);
+        board.set_row(3, 0b01);
+        assert_eq!(Feature::MaxHeightSquared.eval(&board), 16.);
+        assert_eq!(Feature::BumpinessSquared.eval(&board), 4. + 4.);
+        assert_eq!(Feature::Holes.eval(&board), 1.);
+        let mut vec = vec![0.; 16];
+        vec[..3].copy_from_slice(&[1., 2., 3.]);
+        let mut params = params.with_vec(&vec);
+        assert_eq!(params.eval_node(&board), 16. + 16. + 3.);
+        params.features.insert(Feature::RowTransitions, 0.5);
+        assert_eq!(params.eval_node(&board), 16. + 16. + 3. + 0.5 * 8.);
+
+        // A back-to-back tetris in a combo of 2 that sends 6 lines
+        let lock_info = LockInfo {
+            top_out: false,
+            lines_cleared: 4,
+            spin: SpinKind::None,
+            perfect_clear: false,
+            combo: 2,
+            back_to_back: true,
+            attack: 6,
+        };
+        params.normal_clear[4] = 1.;
+        params.attack = 0.5;
+        params.back_to_back = 0.25;
+        params.combo = 0.125;
+        assert_eq!(params.eval_edge(&lock_info), 1. + 3. + 0.25 + 0.25);
+        let lock_info = LockInfo {
+            top_out: true,
+            ..lock_info
+        };
+        assert_eq!(params.eval_edge(&lock_info), f32::NEG_INFINITY);
+
+        assert!(Params::from_toml("normal-clear = [0, 0, 0, 0, 0]").is_err());
+        assert!(Params::from_json(r#"{"features": {"height": 1}}"#).is_err());
+    }
+}
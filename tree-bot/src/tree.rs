@@ -1,10 +1,14 @@
 use anyhow::{bail, Result};
 use core::f32;
-use libtetris::{Board, Fin, Game, Piece, PieceQueue, PieceType};
+use libtetris::{AttackTable, Board, Fin, Game, Piece, PieceQueue, PieceType, SpinMode};
 use smallvec::SmallVec;
 use std::{
+    cmp::Reverse,
     collections::{BinaryHeap, HashMap},
+    error::Error,
+    fmt::{self, Display, Formatter},
     hash::{Hash, Hasher},
+    time::{Duration, Instant},
 };
 
 use crate::param::Params;
@@ -15,6 +19,11 @@ pub struct Node {
     pub active: Piece,
     pub hold: Option<PieceType>,
     pub can_hold: bool,
+    pub hold_disabled: bool,
+    pub combo: u8,
+    pub back_to_back: u8,
+    pub attack_table: AttackTable,
+    pub spin_mode: SpinMode,
     pub step: usize,
     pub score: f32,
 }
@@ -26,6 +35,11 @@ impl Node {
             active: game.active,
             hold: game.hold,
             can_hold: game.can_hold,
+            hold_disabled: game.hold_disabled,
+            combo: game.combo,
+            back_to_back: game.back_to_back,
+            attack_table: game.attack_table,
+            spin_mode: game.spin_mode,
             step,
             score,
         }
@@ -39,13 +53,19 @@ impl Node {
                 queue.len()
             );
         }
-        Ok(Game::from_parts(
+        let mut game = Game::from_parts(
             self.board,
             self.active,
             self.hold,
             &queue[self.step..],
             self.can_hold,
-        ))
+        );
+        game.hold_disabled = self.hold_disabled;
+        game.combo = self.combo;
+        game.back_to_back = self.back_to_back;
+        game.attack_table = self.attack_table;
+        game.spin_mode = self.spin_mode;
+        Ok(game)
     }
 }
 
@@ -55,6 +75,11 @@ impl PartialEq for Node {
             && self.active == other.active
             && self.hold == other.hold
             && self.can_hold == other.can_hold
+            && self.hold_disabled == other.hold_disabled
+            && self.combo == other.combo
+            && self.back_to_back == other.back_to_back
+            && self.attack_table == other.attack_table
+            && self.spin_mode == other.spin_mode
             && self.step == other.step
     }
 }
@@ -67,6 +92,11 @@ impl Hash for Node {
         self.active.hash(state);
         self.hold.hash(state);
         self.can_hold.hash(state);
+        self.hold_disabled.hash(state);
+        self.combo.hash(state);
+        self.back_to_back.hash(state);
+        self.attack_table.hash(state);
+        self.spin_mode.hash(state);
         self.step.hash(state);
     }
 }
@@ -94,25 +124,81 @@ impl Ord for Edge {
     }
 }
 
+/// How the tree is searched. Depths count the node being searched, so a
+/// depth of 1 only scores the node itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Search {
+    /// Depth first search that follows the `take` best edges of every node
+    /// by immediate score
+    Dfs { depth: usize, take: usize },
+    /// Breadth first search that keeps the `width` best lines of every
+    /// layer
+    Beam { depth: usize, width: usize },
+    /// Repeatedly expand the best line found so far, until `nodes` nodes
+    /// have been expanded or the queue runs out
+    BestFirst { nodes: usize },
+}
+
+/// Limits on the nodes expanded and the time spent by searches, a search
+/// fails with [`BudgetExhausted`] once either runs out
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Budget {
+    pub time: Option<Duration>,
+    pub nodes: Option<usize>,
+}
+
+/// The error of a search that ran out of its [`Budget`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BudgetExhausted;
+
+impl Display for BudgetExhausted {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "search budget exhausted")
+    }
+}
+
+impl Error for BudgetExhausted {}
+
 pub struct Tree {
     pub edges: HashMap<Node, SmallVec<[Edge; 64]>>,
     pub queue: Vec<PieceType>,
     pub params: Params,
-    pub dfs_depth: usize,
-    pub dfs_take: usize,
+    pub search: Search,
+    /// Number of nodes expanded since the budget was last set
+    pub nodes: usize,
+    deadline: Option<Instant>,
+    node_limit: Option<usize>,
 }
 
 impl Tree {
-    pub fn new(params: Params, dfs_depth: usize, dfs_take: usize) -> Self {
+    pub fn new(params: Params, search: Search) -> Self {
         Tree {
             edges: HashMap::new(),
             queue: Vec::new(),
             params,
-            dfs_depth,
-            dfs_take,
+            search,
+            nodes: 0,
+            deadline: None,
+            node_limit: None,
         }
     }
 
+    /// Limit the searches that follow, and reset the node count. The clock
+    /// is only read for budgets with a time limit.
+    pub fn set_budget(&mut self, budget: Option<Budget>) {
+        let budget = budget.unwrap_or_default();
+        self.nodes = 0;
+        self.deadline = budget.time.map(|time| Instant::now() + time);
+        self.node_limit = budget.nodes;
+    }
+
+    pub fn out_of_budget(&self) -> bool {
+        self.node_limit.is_some_and(|limit| self.nodes >= limit)
+            || self
+                .deadline
+                .is_some_and(|deadline| Instant::now() >= deadline)
+    }
+
     pub fn clear(&mut self) {
         self.edges.clear();
         self.queue.clear();
@@ -123,7 +209,7 @@ impl Tree {
         let children = game.children(Fin::Simple1);
         let mut nodes = SmallVec::new();
         for child in children {
-            let score = self.params.eval_node(&game.board);
+            let score = self.params.eval_node(&child.game.board);
             let node = Node::new(child.game, node.step + 1, score);
             let score = self.params.eval_edge(&child.lock_info);
             nodes.push(Edge(node, score));
@@ -132,15 +218,56 @@ impl Tree {
         Ok(())
     }
 
+    /// Whether the piece after the node is known, so that its children can
+    /// be found. Lines end at the last piece of the queue.
+    fn can_expand(&self, node: &Node) -> bool {
+        node.step < self.queue.len()
+    }
+
     fn children(&mut self, node: &Node) -> Result<&[Edge]> {
+        if self.out_of_budget() {
+            bail!(BudgetExhausted);
+        }
+        self.nodes += 1;
         if !self.edges.contains_key(node) {
             self.insert(node)?;
         }
         Ok(self.edges.get(node).unwrap())
     }
 
-    fn dfs(&mut self, node: &Node, depth: usize) -> Result<f32> {
-        if depth == self.dfs_depth {
+    /// Score each of the given nodes by searching the lines that follow it
+    pub fn search(&mut self, roots: &[Node]) -> Result<Vec<f32>> {
+        Ok(self.search_with(self.search, roots)?.0)
+    }
+
+    /// Search with the given mode instead of the tree's own, also returns
+    /// the depth reached
+    pub fn search_with(&mut self, search: Search, roots: &[Node]) -> Result<(Vec<f32>, usize)> {
+        match search {
+            Search::Dfs { depth, take } => {
+                let scores = roots
+                    .iter()
+                    .map(|root| self.dfs(root, depth, take))
+                    .collect::<Result<_>>()?;
+                let known = match roots.first() {
+                    Some(root) => self.queue.len().saturating_sub(root.step) + 1,
+                    None => 1,
+                };
+                Ok((scores, depth.min(known)))
+            }
+            Search::Beam { depth, width } => self.beam(roots, depth, width),
+            Search::BestFirst { nodes } => self.best_first(roots, nodes),
+        }
+    }
+
+    pub fn search_game(&mut self, game: &Game, step: usize) -> Result<f32> {
+        let score = self.params.eval_node(&game.board);
+        let node = Node::new(*game, step, score);
+        Ok(self.search(&[node])?[0])
+    }
+
+    fn dfs(&mut self, node: &Node, depth: usize, take: usize) -> Result<f32> {
+        if depth <= 1 || !self.can_expand(node) {
             return Ok(node.score);
         }
 
@@ -149,20 +276,108 @@ impl Tree {
         let mut heap = nodes.into_iter().copied().collect::<BinaryHeap<Edge>>();
 
         let mut max = f32::NEG_INFINITY;
-        for _ in 0..self.dfs_take {
+        for _ in 0..take {
             let Some(Edge(node, edge_score)) = heap.pop() else {
                 break;
             };
-            let score = node.score + edge_score + self.dfs(&node, depth + 1)?;
+            let score = node.score + edge_score + self.dfs(&node, depth - 1, take)?;
             max = max.max(score);
         }
         Ok(max)
     }
 
-    pub fn dfs_game(&mut self, game: &Game, step: usize) -> Result<f32> {
-        let score = self.params.eval_node(&game.board);
-        let node = Node::new(*game, step, score);
-        self.dfs(&node, 1)
+    // Beam and best first search score a line by the sum of its edge scores
+    // plus the node score of its last node. Lines are stored as an edge
+    // holding the last node and the sum of edge scores, so that they are
+    // ordered by their score, along with the index of their root.
+
+    fn beam(&mut self, roots: &[Node], depth: usize, width: usize) -> Result<(Vec<f32>, usize)> {
+        let mut layer = roots
+            .iter()
+            .enumerate()
+            .map(|(i, &root)| (Edge(root, 0.), i))
+            .collect::<Vec<_>>();
+        let mut scores = roots.iter().map(|root| root.score).collect::<Vec<_>>();
+        let mut reached = 1;
+        for _ in 1..depth {
+            // Lines that transpose into the same node are merged
+            let mut next = Vec::<(Edge, usize)>::new();
+            let mut index = HashMap::new();
+            for &(Edge(node, score), root) in layer.iter() {
+                if !self.can_expand(&node) {
+                    continue;
+                }
+                for &Edge(child, edge_score) in self.children(&node)? {
+                    let line = (Edge(child, score + edge_score), root);
+                    match index.get(&child) {
+                        Some(&i) if next[i] >= line => {}
+                        Some(&i) => next[i] = line,
+                        None => {
+                            index.insert(child, next.len());
+                            next.push(line);
+                        }
+                    }
+                }
+            }
+            if next.is_empty() {
+                break;
+            }
+            next.sort_by_key(|&(edge, _)| Reverse(edge));
+            next.truncate(width);
+            layer = next;
+            reached += 1;
+
+            // Roots whose lines all fell out of the beam are never picked
+            scores.fill(f32::NEG_INFINITY);
+            for &(Edge(node, score), root) in layer.iter() {
+                scores[root] = scores[root].max(node.score + score);
+            }
+        }
+        Ok((scores, reached))
+    }
+
+    /// Unlike the other searches, best first search stops early instead of
+    /// failing when the budget runs out
+    fn best_first(&mut self, roots: &[Node], nodes: usize) -> Result<(Vec<f32>, usize)> {
+        let mut frontier = roots
+            .iter()
+            .enumerate()
+            .map(|(i, &root)| (Edge(root, 0.), i))
+            .collect::<BinaryHeap<_>>();
+        let mut scores = vec![f32::NEG_INFINITY; roots.len()];
+        let mut expanded = 0;
+        let mut reached = 1;
+        while expanded < nodes && !self.out_of_budget() {
+            let Some((Edge(node, score), root)) = frontier.pop() else {
+                break;
+            };
+            // The line can't be extended past the end of the queue
+            if !self.can_expand(&node) {
+                scores[root] = scores[root].max(node.score + score);
+                continue;
+            }
+            // The deadline can pass between the check above and expanding
+            let children = match self.children(&node) {
+                Ok(children) => children,
+                Err(err) if err.is::<BudgetExhausted>() => {
+                    frontier.push((Edge(node, score), root));
+                    break;
+                }
+                Err(err) => return Err(err),
+            };
+            expanded += 1;
+            reached = reached.max(node.step - roots[root].step + 2);
+            if children.is_empty() {
+                scores[root] = scores[root].max(node.score + score);
+            }
+            for &Edge(child, edge_score) in children {
+                frontier.push((Edge(child, score + edge_score), root));
+            }
+        }
+        for (Edge(node, score), root) in frontier {
+            scores[root] = scores[root].max(node.score + score);
+        }
+        Ok((scores, reached))
     }
 
     pub fn extend_queue(&mut self, step: usize, pieces: PieceQueue) -> Result<()> {
@@ -186,9 +401,8 @@ impl Tree {
     }
 
     pub fn compactify(&mut self, steps: usize) {
-        for _ in 0..steps {
-            self.queue.remove(0);
-        }
+        // The queue can be shorter than the steps taken without a preview
+        self.queue.drain(..steps.min(self.queue.len()));
 
         let mut new_edges = HashMap::new();
         for (key, val) in &self.edges {
@@ -208,3 +422,111 @@ impl Tree {
         self.edges = new_edges;
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::test_game;
+
+    #[test]
+    fn test_tree_insert() {
+        let mut game = test_game(&[0b1], &[PieceType::O; 4]);
+        game.combo = 3;
+        game.back_to_back = 2;
+        game.attack_table.combo_multiplier = true;
+        game.spin_mode = SpinMode::AllSpin;
+        let search = Search::Dfs { depth: 2, take: 10 };
+        let mut tree = Tree::new(Params::default(), search);
+        tree.extend_queue(0, game.queue).unwrap();
+        let node = Node::new(game, 0, 0.);
+        // Nodes keep the state that attack depends on
+        assert_eq!(node.to_game(&tree.queue).unwrap(), game);
+        let params = tree.params.clone();
+        let edges = tree.children(&node).unwrap();
+        for Edge(child, _) in edges {
+            assert_eq!(child.score, params.eval_node(&child.board));
+        }
+        // Covering the hole next to column 0 is worse than staying flat
+        assert!(edges.iter().any(|edge| edge.0.score != edges[0].0.score));
+    }
+
+    #[test]
+    fn test_tree_dfs() {
+        let game = test_game(&[0b111, 0b1], &[PieceType::T, PieceType::S, PieceType::Z]);
+        let search = Search::Dfs {
+            depth: 2,
+            take: usize::MAX,
+        };
+        let mut tree = Tree::new(Params::default(), search);
+        tree.extend_queue(0, game.queue).unwrap();
+
+        // With every edge taken, a depth 2 search scores each child by its
+        // edge and twice its node score
+        let params = &tree.params;
+        let expected = game
+            .children(Fin::Simple1)
+            .into_iter()
+            .map(|child| {
+                let score = params.eval_node(&child.game.board);
+                score + params.eval_edge(&child.lock_info) + score
+            })
+            .fold(f32::NEG_INFINITY, f32::max);
+        assert_eq!(tree.search_game(&game, 0).unwrap(), expected);
+
+        // Lines end at the last piece of the queue
+        let score = tree.search_game(&game, 1).unwrap();
+        assert_eq!(score, tree.params.eval_node(&game.board));
+    }
+
+    #[test]
+    fn test_tree_search() {
+        // Only the active piece can be placed before the queue runs out
+        let game = test_game(&[0b111, 0b1], &[PieceType::T, PieceType::S, PieceType::Z]);
+        let params = Params::default();
+        let expected = game
+            .children(Fin::Simple1)
+            .into_iter()
+            .map(|child| params.eval_node(&child.game.board) + params.eval_edge(&child.lock_info))
+            .fold(f32::NEG_INFINITY, f32::max);
+
+        let searches = [
+            Search::Beam {
+                depth: 2,
+                width: usize::MAX,
+            },
+            Search::BestFirst { nodes: usize::MAX },
+        ];
+        for search in searches {
+            let mut tree = Tree::new(params.clone(), search);
+            tree.extend_queue(0, game.queue).unwrap();
+            assert_eq!(tree.search_game(&game, 0).unwrap(), expected);
+        }
+
+        // A narrow beam can only miss lines, and best first search with no
+        // budget only scores the root
+        let search = Search::Beam { depth: 2, width: 1 };
+        let mut tree = Tree::new(params.clone(), search);
+        tree.extend_queue(0, game.queue).unwrap();
+        assert!(tree.search_game(&game, 0).unwrap() <= expected);
+        tree.search = Search::BestFirst { nodes: 0 };
+        let score = tree.search_game(&game, 0).unwrap();
+        assert_eq!(score, params.eval_node(&game.board));
+
+        // Expanding a node fails once the budget runs out
+        tree.set_budget(Some(Budget {
+            nodes: Some(0),
+            ..Default::default()
+        }));
+        let root = Node::new(game, 0, 0.);
+        let err = tree.children(&root).unwrap_err();
+        assert!(err.is::<BudgetExhausted>());
+        tree.set_budget(None);
+
+        // Beam search stops at the end of the queue
+        tree.search = Search::Beam {
+            depth: 3,
+            width: usize::MAX,
+        };
+        assert_eq!(tree.search_game(&game, 0).unwrap(), expected);
+    }
+}
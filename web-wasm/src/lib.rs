@@ -1,7 +1,7 @@
-use libtetris::{Ai, Evaluation, Game, SimpleAi};
+use libtetris::{Ai, Evaluation, GameJson, SearchStats, SimpleAi};
 use pc_finder::{PcFinderAi, PcTable};
 use std::sync::{LazyLock, Mutex, OnceLock};
-use tree_bot::{TreeAi, DEFAULT_PARAMS};
+use tree_bot::{Budget, Params, Search, TreeAi};
 use wasm_bindgen::prelude::*;
 
 #[wasm_bindgen]
@@ -18,8 +18,15 @@ pub fn start() {
 
 #[wasm_bindgen]
 pub fn init_pc_finder(pc_table: &[u8]) -> bool {
-    let Ok(table) = PcTable::load(pc_table) else {
-        return false;
+    if PcTable::is_legacy(pc_table) {
+        log("Deprecated: pc table has no header, regenerate it with the pc-finder generator");
+    }
+    let table = match PcTable::load(pc_table) {
+        Ok(table) => table,
+        Err(err) => {
+            log(&format!("Loading pc table failed: {err}"));
+            return false;
+        }
     };
     let ai = PcFinderAi::new(table);
     PC_FINDER_AI.set(Mutex::new(ai)).ok();
@@ -52,14 +59,22 @@ impl ApiEvaluation {
 }
 
 static SIMPLE_AI: LazyLock<Mutex<SimpleAi>> = LazyLock::new(|| Mutex::new(SimpleAi::new()));
-static TREE_AI: LazyLock<Mutex<TreeAi>> =
-    LazyLock::new(|| Mutex::new(TreeAi::new(4, 6, DEFAULT_PARAMS)));
+// The clock isn't available to wasm, so the search is limited by nodes
+static TREE_AI: LazyLock<Mutex<TreeAi>> = LazyLock::new(|| {
+    let budget = Budget {
+        nodes: Some(2000),
+        ..Default::default()
+    };
+    Mutex::new(
+        TreeAi::new(Search::Dfs { depth: 4, take: 6 }, Params::default()).with_budget(budget),
+    )
+});
 static PC_FINDER_AI: OnceLock<Mutex<PcFinderAi>> = OnceLock::new();
 
 #[wasm_bindgen]
 pub fn evaluate(ai_type: String, game: String) -> ApiEvaluation {
-    let game = match serde_json::from_str::<Game>(&game) {
-        Ok(game) => game,
+    let game = match GameJson::from_json(&game) {
+        Ok(json) => json.game,
         Err(err) => {
             return ApiEvaluation {
                 success: false,
@@ -90,13 +105,22 @@ pub fn evaluate(ai_type: String, game: String) -> ApiEvaluation {
         }
     };
     match evaluation {
-        Evaluation::Success { actions, score } => ApiEvaluation {
+        Evaluation::Success {
+            actions,
+            score,
+            stats,
+        } => ApiEvaluation {
             success: true,
             actions: actions
                 .into_iter()
                 .map(|action| action.to_string())
                 .collect(),
-            message: format!("Eval: {score:0.2}"),
+            message: match stats {
+                Some(SearchStats { depth, nodes }) => {
+                    format!("Eval: {score:0.2} Depth: {depth} Nodes: {nodes}")
+                }
+                None => format!("Eval: {score:0.2}"),
+            },
         },
         Evaluation::Fail { message } => ApiEvaluation {
             success: false,